@@ -2,21 +2,97 @@ use crate::{
     convert_error,
     extract::{read_rom_or_iwad, ReadFlags},
     gfx, invalid_data,
+    lock::{Lock, LOCK_NAME},
+    lumps::{ANIMATIONS, SPRITE_ORDER, TEXTURE_ORDER},
+    map::{map_name, map_number, Map},
+    project,
     sound::SoundData,
     wad::{EntryMap, FlatEntry},
-    EntryName, FileFilters, FlatWad, LumpType, Wad, WadEntry, lumps::TEXTURE_ORDER,
+    EntryName, FileFilters, FlatWad, LumpType, Wad, WadEntry,
 };
+use itertools::Itertools;
 use std::{
-    collections::BTreeMap,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
     io,
     path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
 };
 
+/// Map numbers of an input moved to other slots
+type MapSlots = BTreeMap<u16, u16>;
+
+/// Input path, with the maps it provides moved to other slots by a `:maps=` suffix
+#[derive(Clone, Debug)]
+struct Input {
+    path: PathBuf,
+    maps: MapSlots,
+}
+
+impl From<PathBuf> for Input {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            maps: MapSlots::new(),
+        }
+    }
+}
+
+impl std::str::FromStr for Input {
+    type Err = String;
+
+    /// Reads `PATH` or `PATH:maps=MAP01->MAP21,MAP02-MAP09->MAP22`
+    fn from_str(s: &str) -> Result<Self, String> {
+        let Some((path, list)) = s.rsplit_once(":maps=") else {
+            return Ok(PathBuf::from(s).into());
+        };
+        let number = |name: &str| {
+            map_number(&name.trim().to_ascii_uppercase())
+                .ok_or_else(|| format!("`{name}` is not a map name like MAP01"))
+        };
+        let mut maps = MapSlots::new();
+        for pair in list.split(',') {
+            let (from, to) = pair
+                .split_once("->")
+                .ok_or_else(|| format!("`{pair}` is not like MAP01->MAP21"))?;
+            let (first, last) = from.split_once('-').unwrap_or((from, from));
+            let (first, last, to) = (number(first)?, number(last)?, number(to)?);
+            if first > last {
+                return Err(format!("`{from}` ends before it starts"));
+            }
+            for n in first..=last {
+                let slot = to
+                    .checked_add(n - first)
+                    .ok_or_else(|| format!("`{pair}` goes past the last map number"))?;
+                if maps.insert(n, slot).is_some() {
+                    return Err(format!("{} is moved more than once", map_name(n)));
+                }
+            }
+        }
+        let mut slots = HashSet::new();
+        if let Some(slot) = maps.values().find(|&&slot| !slots.insert(slot)) {
+            return Err(format!("more than one map is moved to {}", map_name(*slot)));
+        }
+        Ok(Self {
+            path: path.into(),
+            maps,
+        })
+    }
+}
+
+/// Name a map moves to, if `slots` moves it
+fn moved_map(name: &EntryName, slots: &MapSlots) -> Option<EntryName> {
+    let number = map_number(&name.display())?;
+    EntryName::new(&map_name(*slots.get(&number)?))
+}
+
 #[derive(clap::Args)]
 pub struct Args {
-    /// Directories and ROMs to build into IWAD
+    /// Directories and ROMs to build into IWAD. `PATH:maps=MAP01->MAP21,MAP02-MAP09->MAP22`
+    /// moves the maps of an input to other slots.
     #[arg(required = true)]
-    inputs: Vec<PathBuf>,
+    inputs: Vec<Input>,
     /// IWAD file to output to [default: DOOM64.WAD]
     #[arg(short, long)]
     output: Option<PathBuf>,
@@ -29,9 +105,24 @@ pub struct Args {
     /// Ignore errors when parsing input files
     #[arg(long, default_value_t = false)]
     ignore_errors: bool,
+    /// Treat files with an extension their folder doesn't accept as errors
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+    /// Stop after ignoring this many errors (implies --ignore-errors)
+    #[arg(long)]
+    max_errors: Option<usize>,
     /// Apply minor vanilla asset fixes
     #[arg(long, default_value_t = true)]
     apply_fixes: bool,
+    /// Keep running and rebuild when input files change (implies --ignore-errors)
+    #[arg(short, long, default_value_t = false)]
+    watch: bool,
+    /// Also write the blake3 hash of each output file to this path
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Print the entries and sequences that would be written without writing any files
+    #[arg(short = 'n', long, default_value_t = false, conflicts_with = "watch")]
+    dry_run: bool,
     /// Path to output WDD to [default: DOOM64.WDD]
     #[arg(long)]
     wdd: Option<PathBuf>,
@@ -41,40 +132,277 @@ pub struct Args {
     /// Path to output WSD to [default: DOOM64.WSD]
     #[arg(long)]
     wsd: Option<PathBuf>,
+    /// Regenerate the nodes, blockmap and reject of these maps, or of every map if none are
+    /// given
+    #[arg(
+        long,
+        value_name = "MAPS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    rebuild_nodes: Option<Vec<String>>,
+    /// List textures and flats no map uses, sprites added to the base game whose names it
+    /// never looks up, and sequences numbered past the 117 it plays
+    #[arg(long, default_value_t = false)]
+    report_unused: bool,
+    /// Leave out the textures and flats no map uses, renumbering them in every map
+    #[arg(long, default_value_t = false)]
+    strip_unused: bool,
 }
 
+impl Args {
+    /// Arguments to build `inputs` into `output` as-is, without vanilla fixes
+    pub(crate) fn new(inputs: Vec<PathBuf>, output: PathBuf) -> Self {
+        Self {
+            inputs: inputs.into_iter().map(Input::from).collect(),
+            output: Some(output),
+            exclude: Vec::new(),
+            no_sound: false,
+            ignore_errors: false,
+            strict: false,
+            max_errors: None,
+            apply_fixes: false,
+            watch: false,
+            manifest: None,
+            dry_run: false,
+            wdd: None,
+            wmd: None,
+            wsd: None,
+            rebuild_nodes: None,
+            report_unused: false,
+            strip_unused: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    Load,
+    Merge,
+}
+
+struct SkippedError {
+    file: String,
+    stage: Stage,
+    message: String,
+}
+
+/// Errors skipped with `--ignore-errors`, summarized at the end of a build
+struct ErrorLog {
+    ignore: bool,
+    max: Option<usize>,
+    errors: RefCell<Vec<SkippedError>>,
+}
+
+impl ErrorLog {
+    fn new(ignore: bool, max: Option<usize>) -> Self {
+        Self {
+            ignore: ignore || max.is_some(),
+            max,
+            errors: Default::default(),
+        }
+    }
+    /// Records an error in `file` and carries on, or returns it if errors aren't ignored or
+    /// there are too many of them
+    fn skip(&self, file: impl std::fmt::Display, stage: Stage, err: io::Error) -> io::Result<()> {
+        let file = file.to_string();
+        if !self.ignore {
+            return Err(invalid_data(format_args!("Error reading {file}: {err}")));
+        }
+        log::warn!("Error reading {file}: {err}");
+        let mut errors = self.errors.borrow_mut();
+        errors.push(SkippedError {
+            file,
+            stage,
+            message: err.to_string(),
+        });
+        match self.max {
+            Some(max) if errors.len() > max => Err(invalid_data(format_args!(
+                "Stopping after more than {max} errors"
+            ))),
+            _ => Ok(()),
+        }
+    }
+    /// Prints and clears the skipped errors, returning how many there were
+    fn summarize(&self) -> usize {
+        let errors = self.errors.take();
+        if !errors.is_empty() {
+            log::warn!("Skipped {} files with errors:", errors.len());
+            for SkippedError {
+                file,
+                stage,
+                message,
+            } in &errors
+            {
+                let message = message.lines().next().unwrap_or_default();
+                log::warn!("  {: <5} {file}: {message}", format!("{stage:?}"));
+            }
+        }
+        errors.len()
+    }
+}
+
+#[derive(Clone, Copy)]
 struct LoadOptions<'a> {
     filters: &'a FileFilters,
-    ignore_errors: bool,
+    errors: &'a ErrorLog,
+    strict: bool,
+    /// Lock of the input being loaded, with file paths relative to `root`
+    lock: Option<&'a Lock>,
+    aliases: Option<&'a BTreeMap<String, EntryName>>,
+    map_table: Option<&'a project::MapTable>,
+    root: &'a Path,
+}
+
+/// Files in a directory or archive input that change how the rest of it is loaded
+#[derive(Default)]
+struct InputConfig {
+    lock: Option<Lock>,
+    aliases: BTreeMap<String, EntryName>,
+    map_table: project::MapTable,
+    /// When the manifest and lock of a directory were last modified, for watching
+    modified: [Option<SystemTime>; 2],
+}
+
+impl InputConfig {
+    fn new(lock: Option<Lock>, manifest: Option<project::Manifest>) -> Self {
+        let manifest = manifest.unwrap_or_default();
+        Self {
+            lock,
+            aliases: manifest.aliases,
+            map_table: manifest.map_table,
+            modified: Default::default(),
+        }
+    }
+    fn read(dir: &Path) -> io::Result<Self> {
+        let modified = Self::modified(dir);
+        Ok(Self {
+            modified,
+            ..Self::new(Lock::read(dir)?, project::Manifest::read(dir)?)
+        })
+    }
+    fn modified(dir: &Path) -> [Option<SystemTime>; 2] {
+        [project::MANIFEST_NAME, LOCK_NAME]
+            .map(|name| dir.join(name).metadata().and_then(|m| m.modified()).ok())
+    }
+    fn options<'a>(&'a self, options: &LoadOptions<'a>, root: &'a Path) -> LoadOptions<'a> {
+        LoadOptions {
+            lock: self.lock.as_ref(),
+            aliases: Some(&self.aliases),
+            map_table: Some(&self.map_table),
+            root,
+            ..*options
+        }
+    }
+}
+
+/// A single input file converted into N64 format, ready to be merged
+enum LoadedEntry {
+    Lump(EntryName, WadEntry<Vec<u8>>),
+    Effect(Option<u16>, crate::sound::Sample),
+    Music(Option<u16>, crate::sound::Sequence),
+    SoundFont(PathBuf, bool, Vec<u8>),
+    /// Lump for the map with this name, replacing the map's own once the input is merged
+    MapLump(EntryName, &'static str, Vec<u8>),
+    /// Map converted from a PC format, with the textures and flats its indices refer to
+    ConvertedMap(EntryName, WadEntry<Vec<u8>>, Rc<Vec<(LumpType, EntryName)>>),
+}
+
+impl LoadedEntry {
+    fn apply(&self, wad: &mut Wad, snd: &mut SoundData) -> io::Result<()> {
+        fn next_id(snd: &SoundData) -> u16 {
+            snd.sequences
+                .last_key_value()
+                .map(|p| *p.0 + 1)
+                .unwrap_or_default()
+        }
+        match self {
+            Self::Lump(name, entry) => wad.merge_one(name.clone(), entry.clone())?,
+            Self::ConvertedMap(name, entry, textures) => {
+                wad.merge_one(name.clone(), entry.clone())?;
+                wad.map_textures.insert(name.clone(), textures.clone());
+            }
+            Self::MapLump(name, lump, data) => {
+                let map = wad.maps.get_mut(name).ok_or_else(|| {
+                    invalid_data(format_args!(
+                        "there is no map {} to add {lump} to",
+                        name.display()
+                    ))
+                })?;
+                crate::map::replace_lump(&mut map.data, lump, data.clone());
+            }
+            Self::Effect(id, sample) => {
+                let id = id.unwrap_or_else(|| next_id(snd));
+                snd.sequences
+                    .insert(id, crate::sound::Sequence::Effect(sample.clone()));
+            }
+            Self::Music(id, seq) => {
+                let id = id.unwrap_or_else(|| next_id(snd));
+                snd.sequences.insert(id, seq.clone());
+            }
+            Self::SoundFont(path, is_dls, data) => {
+                let res = if *is_dls {
+                    snd.read_dls(data)
+                } else {
+                    snd.read_sf2(data)
+                };
+                res.map_err(|e| {
+                    invalid_data(format!(
+                        "Failed to load SoundFont `{}`:\n{}",
+                        path.display(),
+                        convert_error(data.as_slice(), e)
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
 }
 
-fn load_entry(
-    wad: &mut Wad,
-    snd: &mut SoundData,
+fn read_entry(
     path: impl AsRef<Path>,
     read: impl FnOnce() -> io::Result<Vec<u8>>,
     base_typ: LumpType,
     options: &LoadOptions,
-) -> io::Result<()> {
+) -> io::Result<Option<LoadedEntry>> {
     use LumpType::*;
 
     let path = path.as_ref();
     let name = match path.file_stem() {
         Some(n) => n,
-        None => return Ok(()),
+        None => return Ok(None),
     };
     let name_str = name.to_string_lossy().to_ascii_uppercase();
-    if name_str.starts_with('.') || name_str.len() > 8 {
-        return Ok(());
+    if name_str.starts_with('.') {
+        log::debug!("Skipping hidden file `{}`", path.display());
+        return Ok(None);
     }
+    let alias = options.aliases.and_then(|aliases| aliases.get(&name_str));
+    let Some(entry_name) = alias
+        .cloned()
+        .or_else(|| EntryName::from_file_name(&name_str))
+    else {
+        log::warn!(
+            "Skipping `{}`: names are limited to 8 characters, \
+            map it to one under [aliases] in {}",
+            path.display(),
+            project::MANIFEST_NAME,
+        );
+        return Ok(None);
+    };
+    let name_str = entry_name.display().into_owned();
     if !options.filters.matches(&name_str) {
         log::debug!("Skipping file `{}`", path.display());
-        return Ok(());
+        return Ok(None);
     }
     let ext = path
         .extension()
         .and_then(|s| s.to_str())
         .map(|s| s.to_ascii_uppercase());
+    if options.strict {
+        check_extension(path, base_typ, ext.as_deref(), options.root)?;
+    }
     let mut typ = match (base_typ, ext.as_deref()) {
         (Sprite, Some("LMP") | Some("PAL")) => Palette,
         (Sequence, Some("SF2") | Some("DLS")) => SoundFont,
@@ -84,6 +412,13 @@ fn load_entry(
         (Unknown, _) if name_str.starts_with("DEMO") => Demo,
         _ => base_typ,
     };
+    let rel = options
+        .lock
+        .and_then(|_| project::relative_name(options.root, path));
+    let locked = rel.as_deref().and_then(|rel| options.lock?.files.get(rel));
+    if let (Unknown, Some(file)) = (base_typ, locked) {
+        typ = file.typ;
+    }
     if typ == Sky {
         if name_str == "FIRE" {
             typ = Fire;
@@ -93,7 +428,18 @@ fn load_entry(
     }
     log::debug!("Reading file `{}` of type {:?}", path.display(), typ);
     let data = read()?;
+    let unchanged = match (options.lock, rel.as_deref()) {
+        (Some(lock), Some(rel)) => lock.is_unchanged(rel, &data),
+        _ => false,
+    };
     let is_png = ext.as_deref() == Some("PNG");
+    let map_lump = match typ {
+        MapLump => Some(
+            map_lump_for(path, options.root)
+                .ok_or_else(|| invalid_data("map lumps must be in a MACROS or LIGHTS folder"))?,
+        ),
+        _ => None,
+    };
     let data = match (typ, is_png) {
         (Palette, _) if ext.as_deref() == Some("PAL") => {
             if data.len() >= 256 * 3 {
@@ -102,7 +448,9 @@ fn load_entry(
                 gfx::palette_rgb_to_16(&data, &mut palette[8..]);
                 palette
             } else {
-                panic!("Palette {name_str} does not have enough entries");
+                return Err(invalid_data(format_args!(
+                    "Palette {name_str} does not have enough entries"
+                )));
             }
         }
         (Graphic | Fire | Cloud, true) => gfx::Graphic::read_png(&data, false)
@@ -114,103 +462,135 @@ fn load_entry(
         (Sprite | HudGraphic | Sky, true) => gfx::Sprite::read_png(&data, None)
             .map_err(invalid_data)?
             .to_vec(),
+        (MapLump, _) => {
+            let lump = map_lump.unwrap();
+            let text = || std::str::from_utf8(&data).map_err(invalid_data);
+            let mut map = crate::map::Map::default();
+            match lump {
+                "LIGHTS" if is_png => map.lights = crate::map::read_lights_png(&data)?,
+                "LIGHTS" => map.lights = crate::map::parse_lights_table(text()?)?,
+                _ => map.macros = Some(crate::map::Macros::parse_script(text()?)?),
+            }
+            let mut data = Vec::new();
+            map.write_lump(lump, &mut data)?;
+            data
+        }
+        (Map, _) if ext.as_deref() == Some("TXT") => {
+            let text = String::from_utf8(data).map_err(invalid_data)?;
+            let wad = crate::map::Map::parse_text(&text, &name_str)?;
+            let mut data = Vec::new();
+            wad.write(&mut data, false)?;
+            data
+        }
         _ => data,
     };
-    match typ {
+    Ok(Some(match typ {
         Sample => {
             let id = name_str
                 .strip_prefix("SFX_")
-                .and_then(|n| str::parse(n).ok())
-                .unwrap_or_else(|| {
-                    snd.sequences
-                        .last_key_value()
-                        .map(|p| *p.0 + 1)
-                        .unwrap_or_default()
-                });
-            let res = crate::sound::Sample::read_file(&data).map_err(|e| {
+                .and_then(|n| str::parse(n).ok());
+            let mut sample = crate::sound::Sample::read_file(&data).map_err(|e| {
                 invalid_data(format!(
                     "Failed to load sound effect `{}`:\n{e}",
                     path.display(),
                 ))
-            });
-            match res {
-                Ok(sample) => {
-                    snd.sequences
-                        .insert(id, crate::sound::Sequence::Effect(sample));
-                }
-                Err(err) => match options.ignore_errors {
-                    true => log::warn!("{err}"),
-                    false => return Err(err),
-                },
-            }
-        }
-        SoundFont => {
-            let res = if ext.as_deref() == Some("DLS") {
-                snd.read_dls(&data)
-            } else {
-                snd.read_sf2(&data)
-            };
-            if let Err(err) = res.map_err(|e| {
-                invalid_data(format!(
-                    "Failed to load SoundFont `{}`:\n{}",
-                    path.display(),
-                    convert_error(data.as_slice(), e)
-                ))
-            }) {
-                match options.ignore_errors {
-                    true => log::warn!("{err}"),
-                    false => return Err(err),
-                }
+            })?;
+            if let (Some(lock), Some(id)) = (options.lock, id) {
+                lock.apply_sound(id, &mut sample, unchanged);
             }
+            LoadedEntry::Effect(id, sample)
         }
+        SoundFont => LoadedEntry::SoundFont(path.to_owned(), ext.as_deref() == Some("DLS"), data),
         Sequence => {
             let id = name_str
                 .strip_prefix("MUS_")
-                .and_then(|n| str::parse(n).ok())
-                .unwrap_or_else(|| {
-                    snd.sequences
-                        .last_key_value()
-                        .map(|p| *p.0 + 1)
-                        .unwrap_or_default()
-                });
+                .and_then(|n| str::parse(n).ok());
             let res = if data.get(0..4) == Some(b"MThd") {
                 crate::music::MusicSequence::read_midi(&mut std::io::Cursor::new(data))
                     .map(crate::sound::Sequence::MusicSeq)
             } else {
-                crate::sound::Sample::read_file(&data).map(|sample| {
+                crate::sound::Sample::read_file(&data).map(|mut sample| {
+                    if let (Some(lock), Some(id)) = (options.lock, id) {
+                        lock.apply_sound(id, &mut sample, unchanged);
+                    }
                     crate::sound::Sequence::MusicSample(crate::music::MusicSample::new(sample))
                 })
             };
-            match res.map_err(|e| {
+            let seq = res.map_err(|e| {
                 invalid_data(format!("Failed to load music `{}`:\n{e}", path.display(),))
-            }) {
-                Ok(seq) => {
-                    snd.sequences.insert(id, seq);
-                }
-                Err(err) => match options.ignore_errors {
-                    true => log::warn!("{err}"),
-                    false => return Err(err),
-                },
-            }
+            })?;
+            LoadedEntry::Music(id, seq)
         }
-        _ => {
-            let mut upper = name_str.replace('^', "\\");
-            upper = upper.replace('@', "?");
-            upper.make_ascii_uppercase();
-            let name = EntryName::new(&upper).unwrap();
-            let entry = WadEntry::new(typ, data);
-            if let Err(err) = wad.merge_one(name, entry) {
-                match options.ignore_errors {
-                    true => log::warn!("{err}"),
-                    false => return Err(err),
-                }
+        MapLump => LoadedEntry::MapLump(entry_name, map_lump.unwrap(), data),
+        Map if ext.as_deref() == Some("WAD") => {
+            let default = project::MapTable::default();
+            let table = options.map_table.unwrap_or(&default);
+            match crate::convert::convert_map(&data, &name_str, table)? {
+                Some((data, textures)) => LoadedEntry::ConvertedMap(
+                    entry_name,
+                    WadEntry::new(typ, data),
+                    Rc::new(textures),
+                ),
+                None => LoadedEntry::Lump(entry_name, WadEntry::new(typ, data)),
             }
         }
+        _ => LoadedEntry::Lump(entry_name, WadEntry::new(typ, data)),
+    }))
+}
+
+/// Lump held by files in one of the top-level folders of map lumps
+fn map_lump_for(path: &Path, root: &Path) -> Option<&'static str> {
+    let rel = project::relative_name(root, path)?;
+    let dir = rel.split('/').next()?.to_ascii_uppercase();
+    ["MACROS", "LIGHTS"].into_iter().find(|lump| *lump == dir)
+}
+
+/// File extensions that `--strict` accepts in the folder for each type, and for map lumps
+/// in the folder of each lump
+pub(crate) fn allowed_extensions(typ: LumpType, map_lump: Option<&str>) -> &'static [&'static str] {
+    use LumpType::*;
+
+    match typ {
+        Sprite => &["PNG", "PAL", "LMP"],
+        Texture | Flat | Graphic | HudGraphic | Sky | Fire | Cloud => &["PNG"],
+        Palette => &["PAL", "LMP"],
+        Sample => &["WAV", "FLAC"],
+        Sequence => &["MID", "WAV", "FLAC", "SF2", "DLS"],
+        Map => &["WAD", "TXT"],
+        MapLump if map_lump == Some("MACROS") => &["TXT"],
+        MapLump => &["TXT", "PNG"],
+        Demo => &["LMP"],
+        Unknown => &["LMP", "PNG", "WAD"],
+        Marker | SoundFont => &[],
+    }
+}
+
+fn check_extension(
+    path: &Path,
+    base_typ: LumpType,
+    ext: Option<&str>,
+    root: &Path,
+) -> io::Result<()> {
+    let in_folder = path
+        .strip_prefix(root)
+        .ok()
+        .and_then(|p| p.parent())
+        .is_some_and(|p| !p.as_os_str().is_empty());
+    if base_typ == LumpType::Unknown && in_folder {
+        return Err(invalid_data("file is not in a recognized folder"));
+    }
+    let allowed = allowed_extensions(base_typ, map_lump_for(path, root));
+    if !ext.is_some_and(|ext| allowed.contains(&ext)) {
+        return Err(invalid_data(format_args!(
+            "extension `{}` is not accepted here, expected {}",
+            ext.unwrap_or_default(),
+            allowed.join(", ")
+        )));
     }
     Ok(())
 }
 
-fn type_for_dir(name: &std::ffi::OsStr) -> Option<LumpType> {
+pub(crate) fn type_for_dir(name: &std::ffi::OsStr) -> Option<LumpType> {
     use LumpType::*;
 
     let upper = name.to_ascii_uppercase();
@@ -223,6 +603,7 @@ fn type_for_dir(name: &std::ffi::OsStr) -> Option<LumpType> {
         Some("HUD") => HudGraphic,
         Some("SKIES") => Sky,
         Some("MAPS") => Map,
+        Some("MACROS") | Some("LIGHTS") => MapLump,
         Some("SOUNDS") => Sample,
         Some("MUSIC") => Sequence,
         Some("DEMOS") => Demo,
@@ -230,14 +611,32 @@ fn type_for_dir(name: &std::ffi::OsStr) -> Option<LumpType> {
     })
 }
 
-fn load_entries(
-    wad: &mut Wad,
-    snd: &mut SoundData,
+struct InputFile {
+    path: PathBuf,
+    typ: LumpType,
+    modified: Option<SystemTime>,
+}
+
+struct LoadedFile {
+    file: InputFile,
+    entry: Option<LoadedEntry>,
+}
+
+enum LoadedInput {
+    /// ROM or IWAD, parsed once and kept as-is
+    Base(Box<Wad>, Option<SoundData>),
+    /// Directory tree, reloaded per file when watching
+    Dir(InputConfig, Vec<LoadedFile>),
+    /// ZIP/PK3 archive, reloaded as a whole when watching
+    Zip(Option<SystemTime>, InputConfig, Vec<LoadedFile>),
+}
+
+fn collect_files(
     path: impl AsRef<Path>,
     meta: Option<std::fs::Metadata>,
     base_typ: LumpType,
     depth: usize,
-    options: &LoadOptions,
+    files: &mut Vec<InputFile>,
 ) -> io::Result<()> {
     let path = path.as_ref();
     let meta = match meta {
@@ -245,14 +644,14 @@ fn load_entries(
         None => path.metadata()?,
     };
     if meta.is_file() {
-        let res = load_entry(wad, snd, path, || std::fs::read(path), base_typ, options);
-        if let Err(err) = res {
-            let err = format!("Error reading {}: {err}", path.display());
-            match options.ignore_errors {
-                true => log::warn!("{err}"),
-                false => return Err(invalid_data(err)),
-            }
+        if depth == 1 && path.file_name().is_some_and(project::is_project_file) {
+            return Ok(());
         }
+        files.push(InputFile {
+            path: path.to_owned(),
+            typ: base_typ,
+            modified: meta.modified().ok(),
+        });
     } else if meta.is_dir() {
         let name = match path.file_name() {
             Some(n) => n,
@@ -263,26 +662,382 @@ fn load_entries(
         } else {
             base_typ
         };
-        let dir = std::fs::read_dir(path)?;
-        for entry in dir.flatten() {
+        // read_dir order depends on the filesystem, sort to make output reproducible
+        let mut dir = std::fs::read_dir(path)?.flatten().collect::<Vec<_>>();
+        dir.sort_by_key(|entry| entry.file_name());
+        for entry in dir {
             let meta = match entry.metadata() {
                 Ok(meta) => meta,
                 Err(_) => continue,
             };
-            load_entries(
-                wad,
-                snd,
-                entry.path(),
-                Some(meta),
-                base_typ,
-                depth + 1,
-                options,
-            )?;
+            collect_files(entry.path(), Some(meta), base_typ, depth + 1, files)?;
         }
     }
     Ok(())
 }
 
+fn load_file(file: InputFile, options: &LoadOptions) -> io::Result<LoadedFile> {
+    let path = file.path.as_path();
+    let res = read_entry(path, || std::fs::read(path), file.typ, options);
+    let entry = match res {
+        Ok(entry) => entry,
+        Err(err) => {
+            options.errors.skip(path.display(), Stage::Load, err)?;
+            None
+        }
+    };
+    Ok(LoadedFile { file, entry })
+}
+
+fn load_zip(input: &Path, options: &LoadOptions) -> io::Result<(InputConfig, Vec<LoadedFile>)> {
+    log::info!("Reading `{}`", input.display());
+    let mut file = std::fs::File::open(input)?;
+    let mut arc = zip::ZipArchive::new(&mut file).map_err(invalid_data)?;
+    let mut read_text = |name: &str| match arc.by_name(name) {
+        Ok(mut afile) => {
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut afile, &mut text)?;
+            Ok(Some(text))
+        }
+        Err(zip::result::ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(invalid_data(e)),
+    };
+    let lock = read_text(LOCK_NAME)?
+        .map(|text| Lock::parse(&text))
+        .transpose()
+        .map_err(|e| invalid_data(format_args!("{}: {LOCK_NAME}: {e}", input.display())))?;
+    let manifest = read_text(project::MANIFEST_NAME)?
+        .map(|text| project::Manifest::parse(&text))
+        .transpose()
+        .map_err(|e| {
+            let name = project::MANIFEST_NAME;
+            invalid_data(format_args!("{}: {name}: {e}", input.display()))
+        })?;
+    let config = InputConfig::new(lock, manifest);
+    let options = &config.options(options, Path::new(""));
+    let mut files = Vec::with_capacity(arc.len());
+    for index in 0..arc.len() {
+        let mut afile = arc.by_index(index).map_err(invalid_data)?;
+        let name = match (afile.is_file(), afile.enclosed_name()) {
+            (true, Some(name)) => name.to_owned(),
+            _ => continue,
+        };
+        if name.parent() == Some(Path::new("")) && project::is_project_file(name.as_os_str()) {
+            continue;
+        }
+        let typ = name
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .last()
+            .and_then(|p| type_for_dir(p.as_os_str()))
+            .unwrap_or(LumpType::Unknown);
+        let res = read_entry(
+            &name,
+            || {
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut afile, &mut data)?;
+                Ok(data)
+            },
+            typ,
+            options,
+        );
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(err) => {
+                let file = format_args!("{}: {}", input.display(), name.display());
+                options.errors.skip(file, Stage::Load, err)?;
+                None
+            }
+        };
+        files.push(LoadedFile {
+            file: InputFile {
+                path: name,
+                typ,
+                modified: None,
+            },
+            entry,
+        });
+    }
+    // archive order depends on the tool that made it, sort like directories are
+    files.sort_by(|a, b| a.file.path.cmp(&b.file.path));
+    Ok((config, files))
+}
+
+fn load_input(
+    input: &Path,
+    no_sound: bool,
+    paths: &crate::extract::ReadPaths,
+    options: &LoadOptions,
+) -> io::Result<LoadedInput> {
+    let ext = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|p| p.to_ascii_lowercase());
+    let ext = ext.as_deref();
+    if ext == Some("z64") || (ext == Some("wad") && !is_map_wad(&input)) {
+        let mut flags = ReadFlags::IWAD | ReadFlags::DECOMPRESS;
+        if !no_sound {
+            flags |= ReadFlags::SOUND;
+        }
+        let (flat, isnd) = read_rom_or_iwad(input, flags, paths)?;
+        let mut flat = flat.unwrap();
+        let textures = Rc::new(flat.texture_names());
+        if !paths.filters.is_empty() {
+            flat.entries
+                .retain(|entry| paths.filters.matches(&entry.name.display()));
+        }
+        let mut wad = Wad::default();
+        wad.merge_flat(flat, |err| {
+            options.errors.skip(input.display(), Stage::Merge, err)
+        })?;
+        for map in wad.maps.keys() {
+            wad.map_textures.insert(map.clone(), textures.clone());
+        }
+        Ok(LoadedInput::Base(Box::new(wad), isnd))
+    } else if ext == Some("zip") || ext == Some("pk3") {
+        let modified = input.metadata()?.modified().ok();
+        let (config, files) = load_zip(input, options)?;
+        Ok(LoadedInput::Zip(modified, config, files))
+    } else {
+        log::info!("Reading `{}`", input.display());
+        let config = InputConfig::read(input)?;
+        let options = config.options(options, input);
+        let mut files = Vec::new();
+        collect_files(input, None, LumpType::Unknown, 0, &mut files)?;
+        let files = files
+            .into_iter()
+            .map(|file| load_file(file, &options))
+            .collect::<io::Result<_>>()?;
+        Ok(LoadedInput::Dir(config, files))
+    }
+}
+
+/// Rescans a watched input, returning `true` if anything changed
+fn refresh_input(
+    input: &Path,
+    loaded: &mut LoadedInput,
+    options: &LoadOptions,
+) -> io::Result<bool> {
+    match loaded {
+        LoadedInput::Base(..) => Ok(false),
+        LoadedInput::Zip(modified, config, files) => {
+            let cur = input.metadata()?.modified().ok();
+            if cur == *modified {
+                return Ok(false);
+            }
+            *modified = cur;
+            (*config, *files) = load_zip(input, options)?;
+            Ok(true)
+        }
+        LoadedInput::Dir(config, files) => {
+            // aliases, the map table and the lock change how every file is loaded
+            let modified = InputConfig::modified(input);
+            let reload = modified != config.modified;
+            if reload {
+                log::info!("Reloading project files of `{}`", input.display());
+                // a broken manifest or lock is only read again once it is saved again
+                config.modified = modified;
+                *config = InputConfig::read(input)?;
+            }
+            let options = &config.options(options, input);
+            let mut scanned = Vec::new();
+            collect_files(input, None, LumpType::Unknown, 0, &mut scanned)?;
+            let mut old = files
+                .drain(..)
+                .map(|f| (f.file.path.clone(), f))
+                .collect::<HashMap<_, _>>();
+            let mut changed = reload;
+            for file in scanned {
+                match old.remove(&file.path) {
+                    Some(loaded) if !reload && loaded.file.modified == file.modified => {
+                        files.push(loaded)
+                    }
+                    _ => {
+                        if !reload {
+                            log::info!("Reloading `{}`", file.path.display());
+                        }
+                        changed = true;
+                        files.push(load_file(file, options)?);
+                    }
+                }
+            }
+            for path in old.keys() {
+                log::info!("Removed `{}`", path.display());
+                changed = true;
+            }
+            Ok(changed)
+        }
+    }
+}
+
+fn merge_inputs(
+    inputs: &[Input],
+    loaded: &[LoadedInput],
+    errors: &ErrorLog,
+) -> io::Result<(Wad, SoundData, Lock)> {
+    let mut iwad = Wad::default();
+    let mut snd = SoundData::default();
+    let mut lock = Lock::default();
+    let mut providers = BTreeMap::<EntryName, Vec<&Path>>::new();
+    for (index, (input, loaded)) in inputs.iter().zip(loaded).enumerate() {
+        let mut provide = |wad: &Wad| {
+            // every map of the base game is expected to be replaced
+            if index > 0 || !matches!(loaded, LoadedInput::Base(..)) {
+                for name in wad.maps.keys() {
+                    providers.entry(name.clone()).or_default().push(&input.path);
+                }
+            }
+        };
+        let (config, files) = match loaded {
+            LoadedInput::Base(wad, isnd) => {
+                let mut wad = Wad::clone(wad);
+                wad.move_maps(&input.maps)?;
+                provide(&wad);
+                let maps = wad.maps.keys().cloned().collect::<Vec<_>>();
+                iwad.merge(wad);
+                iwad.move_exits(&maps, &input.maps)?;
+                if let Some(isnd) = isnd {
+                    snd = isnd.clone();
+                }
+                continue;
+            }
+            LoadedInput::Dir(config, files) | LoadedInput::Zip(_, config, files) => (config, files),
+        };
+        let mut pwad = Wad::default();
+        let mut macros = Vec::new();
+        for LoadedFile { file, entry } in files {
+            let Some(entry) = entry else { continue };
+            // map lumps may belong to maps from earlier inputs
+            if let LoadedEntry::MapLump(..) = entry {
+                macros.push((file, entry));
+                continue;
+            }
+            if let Err(err) = entry.apply(&mut pwad, &mut snd) {
+                errors.skip(file.path.display(), Stage::Merge, err)?;
+            }
+        }
+        pwad.move_maps(&input.maps)?;
+        provide(&pwad);
+        pwad.sort();
+        if let Some(ilock) = &config.lock {
+            pwad.sort_by_lock(ilock);
+            lock.merge(ilock);
+        }
+        let mut maps = pwad.maps.keys().cloned().collect::<Vec<_>>();
+        // converted maps already know which textures they refer to
+        let unconverted = maps
+            .iter()
+            .filter(|name| !pwad.map_textures.contains_key(*name))
+            .cloned()
+            .collect();
+        iwad.merge(pwad);
+        iwad.record_map_textures(unconverted);
+        for (file, entry) in macros {
+            // map lumps move along with the maps of their input
+            let moved;
+            let entry = match entry {
+                LoadedEntry::MapLump(name, lump, data) => match moved_map(name, &input.maps) {
+                    Some(to) => {
+                        maps.push(to.clone());
+                        moved = LoadedEntry::MapLump(to, lump, data.clone());
+                        &moved
+                    }
+                    None => {
+                        maps.push(name.clone());
+                        entry
+                    }
+                },
+                _ => entry,
+            };
+            if let Err(err) = entry.apply(&mut iwad, &mut snd) {
+                errors.skip(file.path.display(), Stage::Merge, err)?;
+            }
+        }
+        maps.sort();
+        maps.dedup();
+        iwad.move_exits(&maps, &input.maps)?;
+    }
+    report_map_conflicts(&providers);
+    Ok((iwad, snd, lock))
+}
+
+/// Warns about map slots that more than one input provides a map for
+fn report_map_conflicts(providers: &BTreeMap<EntryName, Vec<&Path>>) {
+    let conflicts = providers
+        .iter()
+        .filter(|(_, inputs)| inputs.len() > 1)
+        .collect::<Vec<_>>();
+    if conflicts.is_empty() {
+        return;
+    }
+    log::warn!(
+        "{} map slots are provided by more than one input, the last one is used:",
+        conflicts.len()
+    );
+    for (name, inputs) in conflicts {
+        let inputs = inputs.iter().map(|path| format!("`{}`", path.display()));
+        log::warn!("  {: <8} {}", name.display(), inputs.format(", "));
+    }
+}
+
+/// Loads and merges a project directory the same way `build` does, without its base,
+/// returning the paths of the files that contribute entries to it. Fails if any file other
+/// than a hidden one would be skipped, such as one with a long name and no alias.
+pub(crate) fn check_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let paths = crate::extract::ReadPaths::default();
+    let errors = ErrorLog::new(false, None);
+    let options = LoadOptions {
+        filters: &paths.filters,
+        errors: &errors,
+        strict: false,
+        lock: None,
+        aliases: None,
+        map_table: None,
+        root: dir,
+    };
+    let LoadedInput::Dir(config, files) = load_input(dir, true, &paths, &options)? else {
+        unreachable!()
+    };
+    let skipped = files
+        .iter()
+        .filter(|f| f.entry.is_none() && !is_hidden(dir, &f.file.path))
+        .map(|f| format!("\n  {}", f.file.path.display()))
+        .collect::<String>();
+    if !skipped.is_empty() {
+        return Err(invalid_data(format_args!(
+            "These files would be skipped:{skipped}"
+        )));
+    }
+    // map lumps may be for maps of the base, which isn't loaded, and were already parsed
+    let (lumps, files) = files
+        .into_iter()
+        .partition::<Vec<_>, _>(|f| matches!(f.entry, Some(LoadedEntry::MapLump(..))));
+    let input = LoadedInput::Dir(config, files);
+    let inputs = [Input::from(dir.to_owned())];
+    merge_inputs(&inputs, std::slice::from_ref(&input), &errors)?;
+    let LoadedInput::Dir(_, files) = input else {
+        unreachable!()
+    };
+    Ok(files
+        .into_iter()
+        .chain(lumps)
+        .filter(|f| f.entry.is_some())
+        .map(|f| f.file.path)
+        .collect())
+}
+
+/// Returns `true` if the path, or a directory it is in below `root`, starts with a dot
+fn is_hidden(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .any(|c| {
+            c.as_os_str()
+                .to_str()
+                .is_some_and(|name| name.starts_with('.'))
+        })
+}
+
 #[inline]
 fn is_map_wad(path: &impl AsRef<Path>) -> bool {
     if let Some(stem) = path.as_ref().file_stem() {
@@ -295,6 +1050,14 @@ fn is_map_wad(path: &impl AsRef<Path>) -> bool {
 }
 
 impl FlatWad {
+    /// Textures and flats in the order they appear between `T_START` and `T_END`
+    pub(crate) fn texture_names(&self) -> Vec<(LumpType, EntryName)> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.entry.typ, LumpType::Texture | LumpType::Flat))
+            .map(|e| (e.entry.typ, e.name.clone()))
+            .collect()
+    }
     pub fn write(&self, out: &mut impl std::io::Write, verbose: bool) -> io::Result<()> {
         let count =
             u32::try_from(self.entries.len()).map_err(|_| invalid_data("too many entries"))?;
@@ -406,7 +1169,289 @@ impl Wad {
         self.skies.sort_by(name_sort);
         self.other.sort_by(other_sort);
     }
+    /// Restores the entry order recorded in a lock, keeping entries that aren't in it after
+    /// the others in their current order
+    pub(crate) fn sort_by_lock(&mut self, lock: &Lock) {
+        fn sort<T>(entries: &mut EntryMap<T>, lock: &Lock) {
+            entries.sort_by(|ak, a, bk, b| {
+                let a = lock.position(a.typ, ak).unwrap_or(usize::MAX);
+                let b = lock.position(b.typ, bk).unwrap_or(usize::MAX);
+                a.cmp(&b)
+            });
+        }
+        sort(&mut self.maps, lock);
+        sort(&mut self.palettes, lock);
+        sort(&mut self.sprites, lock);
+        sort(&mut self.textures, lock);
+        sort(&mut self.flats, lock);
+        sort(&mut self.graphics, lock);
+        sort(&mut self.hud_graphics, lock);
+        sort(&mut self.skies, lock);
+        sort(&mut self.other, lock);
+    }
+    /// Number of textures written before the flats, which ends with the last texture of the
+    /// base IWAD so that adding textures doesn't move the flats
+    fn textures_before_flats(&self) -> usize {
+        let last = *TEXTURE_ORDER.last().unwrap();
+        self.textures
+            .keys()
+            .position(|name| name.0.as_slice() == last)
+            .map(|p| p + 1)
+            .unwrap_or_else(|| self.textures.len())
+    }
+    /// Textures and flats in the order [`flatten`](Self::flatten) writes them between
+    /// `T_START` and `T_END`, which is the order map texture indices refer to
+    pub(crate) fn texture_names(&self) -> Vec<(LumpType, EntryName)> {
+        let split = self.textures_before_flats();
+        let textures = self.textures.iter().map(|(name, e)| (e.typ, name.clone()));
+        let flats = self.flats.iter().map(|(name, e)| (e.typ, name.clone()));
+        let mut names = Vec::with_capacity(self.textures.len() + self.flats.len());
+        names.extend(textures.clone().take(split));
+        names.extend(flats);
+        names.extend(textures.skip(split));
+        names
+    }
+    /// Records that `maps` refer to textures in the order they are in now
+    fn record_map_textures(&mut self, maps: Vec<EntryName>) {
+        let names = Rc::new(self.texture_names());
+        for map in maps {
+            self.map_textures.insert(map, names.clone());
+        }
+    }
+    /// Rewrites the sector and sidedef texture indices of maps made for a different texture
+    /// order than the one being built, matching textures and flats by name. A name missing
+    /// from its own kind falls back to the first texture or flat with that name, since maps
+    /// converted from PC formats only know whether a name was on a wall or a floor. Maps
+    /// saved in the remaster format have their texture name hashes turned into indices the
+    /// same way.
+    pub(crate) fn remap_map_textures(&mut self) -> io::Result<()> {
+        let names = self.texture_names();
+        let indices = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name, index as u16))
+            .collect::<HashMap<_, _>>();
+        let by_name = names
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, (_, name))| (name, index as u16))
+            .collect::<HashMap<_, _>>();
+        let hashes = crate::remaster::texture_hashes(names.iter().map(|(_, name)| name));
+        for (map_name, entry) in &mut self.maps {
+            let source = self
+                .map_textures
+                .get(map_name)
+                .filter(|source| !names.starts_with(source));
+            let map_name = map_name.display();
+            let mut map = match Map::read(&entry.data) {
+                Ok(map) => map,
+                // maps that can't be read are reported when the maps are checked
+                Err(_) if source.is_none() => continue,
+                Err(e) => {
+                    return Err(invalid_data(format_args!(
+                        "Failed to read map {map_name}: {e}"
+                    )))
+                }
+            };
+            // hashes don't depend on the texture order the map was made for
+            let unhashed = crate::remaster::unhash_map(&mut map, names.len(), &hashes)
+                .map_err(|e| invalid_data(format_args!("{map_name} {e}")))?;
+            if unhashed {
+                log::debug!("Converting texture hashes of {map_name}");
+                map.write(&mut entry.data)?;
+                continue;
+            }
+            let Some(source) = source else {
+                continue;
+            };
+            log::debug!("Remapping texture indices of {map_name}");
+            let remap = |index: &mut u16, record: &str, i: usize| -> io::Result<()> {
+                let Some(name) = source.get(*index as usize) else {
+                    log::warn!(
+                        "{map_name} {record} {i} uses texture {index}, which is out of range"
+                    );
+                    return Ok(());
+                };
+                let found = indices.get(name).or_else(|| by_name.get(&name.1));
+                *index = *found.ok_or_else(|| {
+                    invalid_data(format_args!(
+                        "{map_name} {record} {i} uses {:?} {}, which is not being built",
+                        name.0,
+                        name.1.display()
+                    ))
+                })?;
+                Ok(())
+            };
+            for (i, sector) in map.sectors.iter_mut().enumerate() {
+                remap(&mut sector.floor, "sector", i)?;
+                remap(&mut sector.ceiling, "sector", i)?;
+            }
+            for (i, side) in map.sidedefs.iter_mut().enumerate() {
+                remap(&mut side.top, "sidedef", i)?;
+                remap(&mut side.bottom, "sidedef", i)?;
+                remap(&mut side.middle, "sidedef", i)?;
+            }
+            map.write(&mut entry.data)?;
+        }
+        Ok(())
+    }
+    /// Moves maps to the slots numbered in `slots`, failing if one would replace a map that
+    /// is not moved out of the way
+    fn move_maps(&mut self, slots: &MapSlots) -> io::Result<()> {
+        if slots.is_empty() {
+            return Ok(());
+        }
+        let mut maps = EntryMap::with_capacity(self.maps.len());
+        let mut moved = Vec::new();
+        for (name, entry) in std::mem::take(&mut self.maps) {
+            match moved_map(&name, slots) {
+                Some(to) => moved.push((name, to, entry)),
+                None => {
+                    maps.insert(name, entry);
+                }
+            }
+        }
+        let textures = moved
+            .iter()
+            .map(|(name, ..)| self.map_textures.remove(name))
+            .collect::<Vec<_>>();
+        for ((name, to, entry), textures) in moved.into_iter().zip(textures) {
+            if maps.contains_key(&to) {
+                return Err(invalid_data(format_args!(
+                    "Can't move {} to {}, which has a map that is not moved",
+                    name.display(),
+                    to.display()
+                )));
+            }
+            log::info!("Moving {} to {}", name.display(), to.display());
+            if let Some(textures) = textures {
+                self.map_textures.insert(to.clone(), textures);
+            }
+            maps.insert(to, entry);
+        }
+        self.maps = maps;
+        Ok(())
+    }
+    /// Rewrites the secret exits of `maps` that go to maps moved by `slots`
+    fn move_exits(&mut self, maps: &[EntryName], slots: &MapSlots) -> io::Result<()> {
+        if slots.is_empty() {
+            return Ok(());
+        }
+        for name in maps {
+            let Some(entry) = self.maps.get_mut(name) else {
+                continue;
+            };
+            // maps that can't be read are reported when the maps are checked
+            let Ok(mut map) = Map::read(&entry.data) else {
+                continue;
+            };
+            if map.move_exits(slots) {
+                log::debug!("Rewriting the secret exits of {}", name.display());
+                map.write(&mut entry.data)?;
+            }
+        }
+        Ok(())
+    }
+    /// Textures and flats that no sector or sidedef of any map refers to. Animated textures
+    /// are always kept since the game looks them up by name, and switches and liquid floors
+    /// keep both halves if either one is used.
+    pub(crate) fn unused_textures(&self) -> io::Result<Vec<(LumpType, EntryName)>> {
+        let names = self.texture_names();
+        let mut used = vec![false; names.len()];
+        for (map_name, entry) in &self.maps {
+            let map = Map::read(&entry.data).map_err(|e| {
+                invalid_data(format_args!(
+                    "Failed to read map {}: {e}",
+                    map_name.display()
+                ))
+            })?;
+            let indices = map.sectors.iter().flat_map(|s| [s.floor, s.ceiling]).chain(
+                map.sidedefs
+                    .iter()
+                    .flat_map(|s| [s.top, s.bottom, s.middle]),
+            );
+            for index in indices {
+                if let Some(used) = used.get_mut(index as usize) {
+                    *used = true;
+                }
+            }
+        }
+        for (index, (_, name)) in names.iter().enumerate() {
+            let name = name.0.as_slice();
+            if name == b"?" {
+                used[index] = true;
+            }
+            if let Some(&(_, frames)) = ANIMATIONS.iter().find(|(start, _)| *start == name) {
+                used.iter_mut()
+                    .skip(index)
+                    .take(frames)
+                    .for_each(|u| *u = true);
+            }
+        }
+        // the second texture of a switch or liquid floor directly follows the first, with
+        // the last letter changed from A to B
+        for index in 1..names.len() {
+            let (a, b) = (
+                names[index - 1].1 .0.as_slice(),
+                names[index].1 .0.as_slice(),
+            );
+            let pair = a.len() == b.len()
+                && a.ends_with(b"A")
+                && b.ends_with(b"B")
+                && a[..a.len() - 1] == b[..b.len() - 1];
+            if pair && (used[index - 1] || used[index]) {
+                used[index - 1] = true;
+                used[index] = true;
+            }
+        }
+        Ok(names
+            .into_iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(name, _)| name)
+            .collect())
+    }
+    /// Drops textures and flats, renumbering the ones left in every map
+    pub(crate) fn strip_textures(&mut self, names: &[(LumpType, EntryName)]) -> io::Result<()> {
+        self.record_map_textures(self.maps.keys().cloned().collect());
+        let names = names.iter().collect::<HashSet<_>>();
+        self.textures
+            .retain(|name, entry| !names.contains(&(entry.typ, name.clone())));
+        self.flats
+            .retain(|name, entry| !names.contains(&(entry.typ, name.clone())));
+        self.remap_map_textures()
+    }
+    /// Regenerates the nodes, blockmap and reject of the maps named in `maps`, or of every
+    /// map if it is empty
+    pub(crate) fn rebuild_nodes(&mut self, maps: &[String]) -> io::Result<()> {
+        for name in maps {
+            let found = self
+                .maps
+                .keys()
+                .any(|map| map.display().eq_ignore_ascii_case(name));
+            if !found {
+                return Err(invalid_data(format_args!(
+                    "there is no map {name} to rebuild nodes for"
+                )));
+            }
+        }
+        for (map_name, entry) in &mut self.maps {
+            let map_name = map_name.display();
+            if !maps.is_empty() && !maps.iter().any(|m| m.eq_ignore_ascii_case(&map_name)) {
+                continue;
+            }
+            log::debug!("Building nodes of {map_name}");
+            let mut map = Map::read(&entry.data)
+                .map_err(|e| invalid_data(format_args!("Failed to read map {map_name}: {e}")))?;
+            crate::nodes::build_nodes(&mut map)
+                .map_err(|e| invalid_data(format_args!("{map_name}: {e}")))?;
+            map.write(&mut entry.data)?;
+        }
+        Ok(())
+    }
     pub fn flatten(mut self) -> FlatWad {
+        let texend = self.textures_before_flats();
         let mut flat = FlatWad::default();
         let mut sprite_prefixes = BTreeMap::new();
 
@@ -459,9 +1504,6 @@ impl Wad {
         flat.entries.push(FlatEntry::marker("T_START"));
         flat.entries
             .reserve(self.textures.len() + self.flats.len() + 2);
-        let texend = self.textures.iter().position(|e| &e.0.0 == *TEXTURE_ORDER.last().unwrap())
-            .map(|p| p + 1)
-            .unwrap_or_else(|| self.textures.len());
         let mut textures = self.textures.into_iter();
         let mut i = 0;
         while let Some((name, entry)) = textures.next() {
@@ -505,115 +1547,105 @@ impl Wad {
     }
 }
 
-pub fn build(args: Args) -> io::Result<()> {
-    let Args {
-        inputs,
-        output,
-        exclude,
-        no_sound,
-        ignore_errors,
-        apply_fixes,
-        wdd,
-        wmd,
-        wsd,
-    } = args;
-    let output = output.unwrap_or_else(|| PathBuf::from("DOOM64.WAD"));
-    let mut iwad = Wad::default();
-    let mut snd = SoundData::default();
-    let paths = crate::extract::ReadPaths {
-        filters: crate::FileFilters {
-            includes: Vec::new(),
-            excludes: exclude,
-        },
-        ..Default::default()
-    };
-    let load_options = LoadOptions {
-        filters: &paths.filters,
-        ignore_errors,
-    };
-    for input in inputs {
-        let ext = input
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|p| p.to_ascii_lowercase());
-        let ext = ext.as_deref();
-        if ext == Some("z64") || (ext == Some("wad") && !is_map_wad(&input)) {
-            let mut flags = ReadFlags::IWAD | ReadFlags::DECOMPRESS;
-            if !no_sound {
-                flags |= ReadFlags::SOUND;
-            }
-            let (flat, isnd) = read_rom_or_iwad(input, flags, &paths)?;
-            let mut flat = flat.unwrap();
-            if !paths.filters.is_empty() {
-                flat.entries
-                    .retain(|entry| paths.filters.matches(&entry.name.display()));
-            }
-            iwad.merge_flat(flat, ignore_errors)?;
-            if let Some(isnd) = isnd {
-                snd = isnd;
-            }
-        } else if ext == Some("zip") || ext == Some("pk3") {
-            log::info!("Reading `{}`", input.display());
-            let mut file = std::fs::File::open(&input)?;
-            let mut arc = zip::ZipArchive::new(&mut file).map_err(invalid_data)?;
-            let mut pwad = Wad::default();
-            for index in 0..arc.len() {
-                let mut afile = arc.by_index(index).map_err(invalid_data)?;
-                let name = match (afile.is_file(), afile.enclosed_name()) {
-                    (true, Some(name)) => name.to_owned(),
-                    _ => continue,
-                };
-                let typ = name
-                    .ancestors()
-                    .filter(|p| !p.as_os_str().is_empty())
-                    .last()
-                    .and_then(|p| type_for_dir(p.as_os_str()))
-                    .unwrap_or(LumpType::Unknown);
-                let res = load_entry(
-                    &mut pwad,
-                    &mut snd,
-                    &name,
-                    || {
-                        let mut data = Vec::new();
-                        std::io::Read::read_to_end(&mut afile, &mut data)?;
-                        Ok(data)
-                    },
-                    typ,
-                    &load_options,
-                );
-                if let Err(err) = res {
-                    let err = format!(
-                        "Error reading {}: {}: {err}",
-                        input.display(),
-                        name.display()
-                    );
-                    match ignore_errors {
-                        true => log::warn!("{err}"),
-                        false => return Err(invalid_data(err)),
-                    }
-                }
-            }
-            pwad.sort();
-            iwad.merge(pwad);
-        } else {
-            log::info!("Reading `{}`", input.display());
-            let mut pwad = Wad::default();
-            load_entries(
-                &mut pwad,
-                &mut snd,
-                input,
-                None,
-                LumpType::Unknown,
-                0,
-                &load_options,
-            )?;
-            pwad.sort();
-            iwad.merge(pwad);
+struct Outputs {
+    output: PathBuf,
+    wdd: PathBuf,
+    wmd: PathBuf,
+    wsd: PathBuf,
+    manifest: Option<PathBuf>,
+    no_sound: bool,
+    apply_fixes: bool,
+    rebuild_nodes: Option<Vec<String>>,
+    report_unused: bool,
+    strip_unused: bool,
+}
+
+/// Points sprites back at the palette they shared in an extracted IWAD, as long as the
+/// palettes are still identical
+fn share_palettes(flat: &mut FlatWad, lock: &Lock) {
+    fn colors(palette: &gfx::SpritePalette) -> Option<&[gfx::RGBA]> {
+        match palette {
+            gfx::SpritePalette::Rgb8(palette) => Some(palette.as_slice()),
+            gfx::SpritePalette::Rgb4(palette) => Some(palette.as_slice()),
+            gfx::SpritePalette::Offset(_) => None,
+        }
+    }
+    fn parse_sprite(data: &[u8]) -> Option<gfx::Sprite> {
+        gfx::Sprite::parse::<nom::error::Error<_>>(data)
+            .ok()
+            .map(|r| r.1)
+    }
+
+    if lock.palettes.is_empty() {
+        return;
+    }
+    let mut positions = HashMap::new();
+    for index in 0..flat.entries.len() {
+        let FlatEntry { name, entry } = &flat.entries[index];
+        if !matches!(entry.typ, LumpType::Sprite | LumpType::Palette) {
+            continue;
+        }
+        positions.insert(name.clone(), index);
+        let Some(source) = lock.palettes.get(name) else {
+            continue;
+        };
+        let Some(source_index) = positions.get(source).copied() else {
+            continue;
+        };
+        let Ok(offset) = u16::try_from(index - source_index) else {
+            continue;
+        };
+        let Some(mut sprite) = parse_sprite(&entry.data) else {
+            continue;
+        };
+        let gfx::SpritePalette::Rgb8(palette) = &sprite.palette else {
+            continue;
+        };
+        let source = &flat.entries[source_index].entry;
+        let matches = match source.typ {
+            LumpType::Palette => source.data.get(8..8 + 256 * 2).is_some_and(|data| {
+                let mut colors = [gfx::RGBA::default(); 256];
+                gfx::palette_16_to_rgba(data, &mut colors);
+                colors == **palette
+            }),
+            _ => parse_sprite(&source.data)
+                .is_some_and(|s| colors(&s.palette) == Some(palette.as_slice())),
+        };
+        if matches {
+            sprite.palette = gfx::SpritePalette::Offset(offset);
+            flat.entries[index].entry.data = sprite.to_vec();
+        }
+    }
+}
+
+/// Lays out merged entries exactly as they will be written to the IWAD
+fn flatten_outputs(
+    mut iwad: Wad,
+    snd: &SoundData,
+    lock: &Lock,
+    sources: Option<&Sources>,
+    outputs: &Outputs,
+) -> io::Result<FlatWad> {
+    iwad.remap_map_textures()?;
+    if let Some(maps) = &outputs.rebuild_nodes {
+        iwad.rebuild_nodes(maps)?;
+    }
+    if outputs.report_unused || outputs.strip_unused {
+        let unused = iwad.unused_textures()?;
+        if outputs.report_unused {
+            let sources = sources.expect("sources are collected for --report-unused");
+            report_unused(&iwad, &unused, snd, sources);
+        }
+        if outputs.strip_unused && !unused.is_empty() {
+            log::info!("Leaving out {} unused textures and flats", unused.len());
+            iwad.strip_textures(&unused)?;
         }
     }
     let mut flat = iwad.flatten();
+    check_maps(&flat)?;
+    share_palettes(&mut flat, lock);
     for entry in &mut flat.entries {
-        if apply_fixes {
+        if outputs.apply_fixes {
             if let Some((hash, fixes)) = crate::lumps::VANILLA_FIXES.get(&entry.name.0) {
                 if hash == blake3::hash(&entry.entry.data).as_bytes() {
                     for (offset, patch) in fixes.iter().copied() {
@@ -627,20 +1659,222 @@ pub fn build(args: Args) -> io::Result<()> {
                 }
             }
         }
+        let compression = lock.compression(entry.entry.typ, &entry.name);
+        entry.entry.compress(compression);
+    }
+    Ok(flat)
+}
+
+/// Where each merged entry was loaded from, for `--dry-run`, and whether that was an input
+/// added to the base game, for `--report-unused`
+#[derive(Default)]
+struct Sources(HashMap<(LumpType, EntryName), (String, bool)>);
+
+impl Sources {
+    fn new(inputs: &[Input], loaded: &[LoadedInput]) -> Self {
+        fn insert<T>(sources: &mut Sources, map: &EntryMap<T>, input: &Input, added: bool) {
+            for (name, entry) in map {
+                let source = input.path.display().to_string();
+                sources.add(entry.typ, name, &input.maps, source, added);
+            }
+        }
+        let mut sources = Self::default();
+        for (index, (input, loaded)) in inputs.iter().zip(loaded).enumerate() {
+            // same as for map conflicts, only a ROM or IWAD given first is the base game
+            let added = index > 0 || !matches!(loaded, LoadedInput::Base(..));
+            let files = match loaded {
+                LoadedInput::Base(wad, _) => {
+                    insert(&mut sources, &wad.maps, input, added);
+                    insert(&mut sources, &wad.palettes, input, added);
+                    insert(&mut sources, &wad.sprites, input, added);
+                    insert(&mut sources, &wad.textures, input, added);
+                    insert(&mut sources, &wad.flats, input, added);
+                    insert(&mut sources, &wad.graphics, input, added);
+                    insert(&mut sources, &wad.hud_graphics, input, added);
+                    insert(&mut sources, &wad.skies, input, added);
+                    insert(&mut sources, &wad.other, input, added);
+                    continue;
+                }
+                LoadedInput::Dir(_, files) | LoadedInput::Zip(_, _, files) => files,
+            };
+            for LoadedFile { file, entry } in files {
+                if let Some(
+                    LoadedEntry::Lump(name, entry) | LoadedEntry::ConvertedMap(name, entry, _),
+                ) = entry
+                {
+                    // paths in archives are relative to the archive itself
+                    let source = input.path.join(&file.path).display().to_string();
+                    sources.add(entry.typ, name, &input.maps, source, added);
+                }
+            }
+        }
+        sources
+    }
+    fn add(
+        &mut self,
+        typ: LumpType,
+        name: &EntryName,
+        slots: &MapSlots,
+        source: String,
+        added: bool,
+    ) {
+        // maps are listed under the slots they were moved to
+        let moved = match typ {
+            LumpType::Map => moved_map(name, slots),
+            _ => None,
+        };
+        self.0.insert(
+            (typ, moved.unwrap_or_else(|| name.clone())),
+            (source, added),
+        );
+    }
+    fn get(&self, typ: LumpType, name: &EntryName) -> &str {
+        self.0
+            .get(&(typ, name.clone()))
+            .map(|s| s.0.as_str())
+            .unwrap_or("-")
+    }
+    /// Whether the entry was last replaced by an input other than the base game
+    fn added(&self, typ: LumpType, name: &EntryName) -> bool {
+        self.0.get(&(typ, name.clone())).is_some_and(|s| s.1)
+    }
+}
+
+/// Number of sequences in the base game, which it plays by number
+const BASE_SEQUENCES: u16 = 117;
+
+/// Logs the textures and flats no map uses, the sprites added to the base game that it never
+/// looks up because their names are not ones it knows, and the sequences numbered after the
+/// base game's
+fn report_unused(
+    iwad: &Wad,
+    textures: &[(LumpType, EntryName)],
+    snd: &SoundData,
+    sources: &Sources,
+) {
+    log::info!(
+        "{} textures and flats are not used by any map",
+        textures.len()
+    );
+    for (typ, name) in textures {
+        log::info!("  {: <8} {typ:?}", name.display());
+    }
+    let known = SPRITE_ORDER
+        .iter()
+        .map(|name| &name[..4])
+        .collect::<HashSet<_>>();
+    let sprites = iwad
+        .sprites
+        .iter()
+        .filter(|(name, entry)| sources.added(entry.typ, name))
+        .map(|(name, _)| name)
+        .filter(|name| name.0.len() >= 4 && !known.contains(&name.0[..4]))
+        .collect::<Vec<_>>();
+    log::info!(
+        "{} added sprites are not looked up by the game",
+        sprites.len()
+    );
+    for name in sprites {
+        log::info!("  {}", name.display());
+    }
+    let sequences = snd
+        .sequences
+        .keys()
+        .filter(|&&id| id >= BASE_SEQUENCES)
+        .collect::<Vec<_>>();
+    log::info!(
+        "{} sequences are not played by the game, which only knows the first {BASE_SEQUENCES}",
+        sequences.len()
+    );
+    for id in sequences {
+        log::info!("  {id:03}");
+    }
+}
+
+/// Prints what `write_outputs` would write, without writing anything
+fn print_plan(flat: &FlatWad, snd: &SoundData, sources: &Sources, outputs: &Outputs) {
+    let size = flat
+        .entries
+        .iter()
+        .map(|e| e.entry.padded_len().unwrap_or_default() as usize + 16)
+        .sum::<usize>()
+        + 0xc;
+    log::info!(
+        "Would write `{}` with {} entries, 0x{size:x} bytes",
+        outputs.output.display(),
+        flat.entries.len()
+    );
+    log::info!("  INDEX NAME     TYPE        SIZE       SOURCE");
+    for (index, FlatEntry { name, entry }) in flat.entries.iter().enumerate() {
+        let source = match entry.typ {
+            LumpType::Marker => "",
+            typ => sources.get(typ, name),
+        };
+        log::info!(
+            "  {index: <5} {: <8} {: <11} 0x{: <8x} {source}",
+            name.display(),
+            format!("{:?}", entry.typ),
+            entry.data.len(),
+        );
+    }
+    if outputs.no_sound {
+        return;
+    }
+    log::info!(
+        "Would write `{}` with {} instruments",
+        outputs.wmd.display(),
+        snd.instruments.len()
+    );
+    log::info!("  PATCH PATCHMAPS SAMPLES");
+    for (index, inst) in &snd.instruments {
+        log::info!(
+            "  {index: <5} {: <9} {}",
+            inst.patchmaps.len(),
+            inst.patchmaps.iter().map(|p| p.sample_id).format(","),
+        );
+    }
+    log::info!(
+        "Would write `{}` with {} sequences",
+        outputs.wsd.display(),
+        snd.sequences.len()
+    );
+    log::info!("  SEQ   TYPE   PRI TRACKS SAMPLES");
+    for (index, seq) in &snd.sequences {
+        match seq {
+            crate::sound::Sequence::Effect(sample) => log::info!(
+                "  {index: <5} Effect {: <3} -      {}",
+                sample.priority,
+                sample.info.samples.n_samples(),
+            ),
+            crate::sound::Sequence::MusicSeq(seq) => {
+                log::info!("  {index: <5} Music  -   {: <6} -", seq.tracks.len())
+            }
+            crate::sound::Sequence::MusicSample(_) => {}
+        }
     }
+}
+
+fn write_outputs(
+    iwad: Wad,
+    mut snd: SoundData,
+    lock: &Lock,
+    sources: Option<&Sources>,
+    outputs: &Outputs,
+) -> io::Result<()> {
+    let flat = flatten_outputs(iwad, &snd, lock, sources, outputs)?;
     log::info!(
         "Writing `{}` with {} entries",
-        output.display(),
+        outputs.output.display(),
         flat.entries.len()
     );
     log::debug!("  SIZE       NAME     HASH");
     {
-        let out = std::fs::File::create(&output)?;
+        let out = std::fs::File::create(&outputs.output)?;
         let mut out = std::io::BufWriter::new(out);
         flat.write(&mut out, crate::is_log_level(log::LevelFilter::Debug))?;
     }
 
-    if !no_sound {
+    if !outputs.no_sound {
         snd.compress();
         let mut sample_count = 0u32;
         snd.foreach_sample(|_| {
@@ -649,41 +1883,542 @@ pub fn build(args: Args) -> io::Result<()> {
         })
         .unwrap();
         {
-            let filename = wdd.unwrap_or_else(|| output.with_extension("WDD"));
             log::info!(
                 "Writing `{}` with {sample_count} samples",
-                filename.display(),
+                outputs.wdd.display(),
             );
-            let out = std::fs::File::create(filename)?;
+            let out = std::fs::File::create(&outputs.wdd)?;
             let mut out = std::io::BufWriter::new(out);
             snd.write_wdd(&mut out)?;
             drop(out);
         }
 
         {
-            let filename = wmd.unwrap_or_else(|| output.with_extension("WMD"));
             log::info!(
                 "Writing `{}` with {} instruments",
-                filename.display(),
+                outputs.wmd.display(),
                 snd.instruments.len(),
             );
-            let out = std::fs::File::create(filename)?;
+            let out = std::fs::File::create(&outputs.wmd)?;
             let mut out = std::io::BufWriter::new(out);
             snd.write_wmd(&mut out)?;
         }
 
         {
-            let filename = wsd.unwrap_or_else(|| output.with_extension("WSD"));
             log::info!(
                 "Writing `{}` with {} sequences",
-                filename.display(),
+                outputs.wsd.display(),
                 snd.sequences.len()
             );
-            let out = std::fs::File::create(filename)?;
+            let out = std::fs::File::create(&outputs.wsd)?;
             let mut out = std::io::BufWriter::new(out);
             snd.write_wsd(&mut out)?;
         }
     }
 
+    if let Some(manifest) = &outputs.manifest {
+        let mut files = vec![outputs.output.as_path()];
+        if !outputs.no_sound {
+            files.extend([&outputs.wdd, &outputs.wmd, &outputs.wsd].map(|p| p.as_path()));
+        }
+        log::info!("Writing `{}`", manifest.display());
+        crate::verify::write_manifest(manifest, &files)?;
+    }
+
+    Ok(())
+}
+
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+fn watch(
+    inputs: &[Input],
+    loaded: &mut [LoadedInput],
+    options: &LoadOptions,
+    outputs: &Outputs,
+) -> io::Result<()> {
+    log::info!("Watching for changes, press Ctrl+C to stop");
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        let mut changed = false;
+        for (input, loaded) in inputs.iter().zip(loaded.iter_mut()) {
+            match refresh_input(&input.path, loaded, options) {
+                Ok(c) => changed |= c,
+                Err(err) => log::error!("Error reading {}: {err}", input.path.display()),
+            }
+        }
+        if !changed {
+            continue;
+        }
+        let sources = outputs.report_unused.then(|| Sources::new(inputs, loaded));
+        let res = merge_inputs(inputs, loaded, options.errors).and_then(|(iwad, snd, lock)| {
+            write_outputs(iwad, snd, &lock, sources.as_ref(), outputs)
+        });
+        options.errors.summarize();
+        match res {
+            Ok(()) => log::info!("Rebuild finished"),
+            Err(err) => log::error!("{err}"),
+        }
+    }
+}
+
+/// Loads the base ROM or IWAD named in the manifest of a project given as the first input,
+/// resolving a relative path from the project
+fn add_project_base(inputs: &mut Vec<Input>) -> io::Result<()> {
+    if inputs[0].path.is_dir() {
+        let base = project::Manifest::read(&inputs[0].path)?.and_then(|m| m.base);
+        if let Some(base) = base.map(|base| inputs[0].path.join(base)) {
+            log::info!("Using base `{}` from project manifest", base.display());
+            inputs.insert(0, base.into());
+        }
+    }
     Ok(())
 }
+
+/// Logs every problem found in the maps of a flattened IWAD, returning an error if there
+/// were any
+fn check_maps(flat: &FlatWad) -> io::Result<()> {
+    let problems = crate::map::validate_maps(flat);
+    for problem in &problems {
+        log::error!("{problem}");
+    }
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(invalid_data(format_args!("Found {n} problems in maps"))),
+    }
+}
+
+/// Loads and merges inputs the same way `build` does without writing anything, carrying on
+/// after errors so they can all be reported, then checks the maps of the result
+pub(crate) fn lint(inputs: Vec<PathBuf>, exclude: Vec<String>, strict: bool) -> io::Result<()> {
+    let mut inputs = inputs.into_iter().map(Input::from).collect();
+    add_project_base(&mut inputs)?;
+    let paths = crate::extract::ReadPaths {
+        filters: crate::FileFilters {
+            includes: Vec::new(),
+            excludes: exclude,
+        },
+        ..Default::default()
+    };
+    let errors = ErrorLog::new(true, None);
+    let options = LoadOptions {
+        filters: &paths.filters,
+        errors: &errors,
+        strict,
+        lock: None,
+        aliases: None,
+        map_table: None,
+        root: Path::new(""),
+    };
+    let res = inputs
+        .iter()
+        .map(|input| load_input(&input.path, true, &paths, &options))
+        .collect::<io::Result<Vec<_>>>()
+        .and_then(|loaded| merge_inputs(&inputs, &loaded, &errors))
+        .and_then(|(mut iwad, _, _)| {
+            iwad.remap_map_textures()?;
+            check_maps(&iwad.flatten())
+        });
+    let skipped = errors.summarize();
+    res?;
+    match skipped {
+        0 => {
+            log::info!("No problems found");
+            Ok(())
+        }
+        n => Err(io::Error::other(crate::SkippedErrors(n))),
+    }
+}
+
+pub fn build(args: Args) -> io::Result<()> {
+    let Args {
+        inputs,
+        output,
+        exclude,
+        no_sound,
+        ignore_errors,
+        strict,
+        max_errors,
+        apply_fixes,
+        watch: watch_inputs,
+        manifest,
+        dry_run,
+        wdd,
+        wmd,
+        wsd,
+        rebuild_nodes,
+        report_unused,
+        strip_unused,
+    } = args;
+    let mut inputs = inputs;
+    add_project_base(&mut inputs)?;
+    let output = output.unwrap_or_else(|| PathBuf::from("DOOM64.WAD"));
+    let outputs = Outputs {
+        wdd: wdd.unwrap_or_else(|| output.with_extension("WDD")),
+        wmd: wmd.unwrap_or_else(|| output.with_extension("WMD")),
+        wsd: wsd.unwrap_or_else(|| output.with_extension("WSD")),
+        output,
+        manifest,
+        no_sound,
+        apply_fixes,
+        rebuild_nodes,
+        report_unused,
+        strip_unused,
+    };
+    let paths = crate::extract::ReadPaths {
+        filters: crate::FileFilters {
+            includes: Vec::new(),
+            excludes: exclude,
+        },
+        ..Default::default()
+    };
+    let errors = ErrorLog::new(ignore_errors || watch_inputs, max_errors);
+    let load_options = LoadOptions {
+        filters: &paths.filters,
+        errors: &errors,
+        strict,
+        lock: None,
+        aliases: None,
+        map_table: None,
+        root: Path::new(""),
+    };
+    let loaded = inputs
+        .iter()
+        .map(|input| load_input(&input.path, no_sound, &paths, &load_options))
+        .collect::<io::Result<Vec<_>>>();
+    let mut loaded = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            errors.summarize();
+            return Err(err);
+        }
+    };
+    // only used to print the plan and report unused entries
+    let sources = (dry_run || outputs.report_unused).then(|| Sources::new(&inputs, &loaded));
+    if watch_inputs {
+        let res = merge_inputs(&inputs, &loaded, &errors).and_then(|(iwad, snd, lock)| {
+            write_outputs(iwad, snd, &lock, sources.as_ref(), &outputs)
+        });
+        errors.summarize();
+        if let Err(err) = res {
+            log::error!("{err}");
+        }
+        return watch(&inputs, &mut loaded, &load_options, &outputs);
+    }
+    let res = merge_inputs(&inputs, &loaded, &errors).and_then(|(iwad, snd, lock)| {
+        if dry_run {
+            let flat = flatten_outputs(iwad, &snd, &lock, sources.as_ref(), &outputs)?;
+            print_plan(&flat, &snd, sources.as_ref().unwrap(), &outputs);
+            return Ok(());
+        }
+        drop(loaded);
+        write_outputs(iwad, snd, &lock, sources.as_ref(), &outputs)
+    });
+    let skipped = errors.summarize();
+    res?;
+    match skipped {
+        0 => Ok(()),
+        n => Err(io::Error::other(crate::SkippedErrors(n))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> EntryName {
+        EntryName::new(name).unwrap()
+    }
+
+    fn base_wad(textures: &[&str], flats: &[&str]) -> Wad {
+        let texture = |typ| {
+            let texture = gfx::Texture {
+                wshift: 0,
+                hshift: 0,
+                data: Vec::new(),
+                palettes: Vec::new(),
+            };
+            WadEntry::new(typ, texture)
+        };
+        let mut wad = Wad::default();
+        for t in textures {
+            wad.textures.insert(name(t), texture(LumpType::Texture));
+        }
+        for f in flats {
+            wad.flats.insert(name(f), texture(LumpType::Flat));
+        }
+        wad
+    }
+
+    fn load(path: &str, data: Vec<u8>, typ: LumpType) -> LoadedFile {
+        let filters = FileFilters::default();
+        let errors = ErrorLog::new(false, None);
+        let options = LoadOptions {
+            filters: &filters,
+            errors: &errors,
+            strict: false,
+            lock: None,
+            aliases: None,
+            map_table: None,
+            root: Path::new("mod"),
+        };
+        let path = Path::new("mod").join(path);
+        let entry = read_entry(&path, || Ok(data), typ, &options).unwrap();
+        let file = InputFile {
+            path,
+            typ,
+            modified: None,
+        };
+        LoadedFile { file, entry }
+    }
+
+    fn effect(pitch: i32, samples: Vec<i16>) -> Vec<u8> {
+        let sample = crate::sound::Sample {
+            info: crate::sound::PatchInfo {
+                samples: crate::sound::SampleData::Raw(samples),
+                pitch,
+                r#loop: None,
+            },
+            priority: 50,
+            volume: 100,
+        };
+        let mut data = Vec::new();
+        sample.write_wav(&mut data).unwrap();
+        data
+    }
+
+    fn midi(notes: &[u8]) -> Vec<u8> {
+        let mut track = vec![0x00, 0xc0, 0x00, 0x00, 0xc1, 0x01];
+        for (i, &note) in notes.iter().enumerate() {
+            let channel = i as u8 & 1;
+            track.extend([0x00, 0x90 | channel, note, 0x60]);
+            track.extend([0x30, 0x80 | channel, note, 0x00]);
+        }
+        track.extend([0x00, 0xff, 0x2f, 0x00]);
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        data.extend((track.len() as u32).to_be_bytes());
+        data.extend(track);
+        data
+    }
+
+    /// Builds a project or, with `zip`, an archive holding `files`, adding them in the order
+    /// given
+    fn build_files(dir: &Path, files: &[(&str, Vec<u8>)], zip: bool) -> Vec<Vec<u8>> {
+        let _ = std::fs::remove_dir_all(dir);
+        let project = dir.join(if zip { "project.zip" } else { "project" });
+        if zip {
+            std::fs::create_dir_all(dir).unwrap();
+            let file = std::fs::File::create(&project).unwrap();
+            let mut archive = zip::ZipWriter::new(file);
+            for (path, data) in files {
+                let options = zip::write::FileOptions::default();
+                archive.start_file(*path, options).unwrap();
+                io::Write::write_all(&mut archive, data).unwrap();
+            }
+            archive.finish().unwrap();
+        } else {
+            for (path, data) in files {
+                let path = project.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, data).unwrap();
+            }
+        }
+        let output = dir.join("DOOM64.WAD");
+        build(Args::new(vec![project], output.clone())).unwrap();
+        let outputs = ["WAD", "WMD", "WSD", "WDD"]
+            .map(|ext| std::fs::read(output.with_extension(ext)).unwrap())
+            .to_vec();
+        std::fs::remove_dir_all(dir).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn file_order() {
+        let mut map_data = Vec::new();
+        let map = Map::default().to_wad("MAP01").unwrap();
+        map.write(&mut map_data, false).unwrap();
+        let mut files = vec![
+            ("MAPS/MAP01.WAD", map_data),
+            ("SOUNDS/SFX_001.WAV", effect(0, vec![100; 64])),
+            ("SOUNDS/ALARM.WAV", effect(-1200, vec![-200; 48])),
+            ("SOUNDS/BEEP.WAV", effect(1200, vec![300; 32])),
+            ("MUSIC/THEME.MID", midi(&[60, 64, 67])),
+            ("MUSIC/MUS_120.MID", midi(&[48, 52])),
+            ("DEMOS/DEMO1LMP.LMP", vec![1, 2, 3]),
+            ("ENDOOM.LMP", vec![4; 16]),
+            ("ANOTHER.LMP", vec![5; 8]),
+        ];
+        let dir = std::env::temp_dir().join(format!("d64make-order-{}", std::process::id()));
+        let outputs = build_files(&dir, &files, false);
+        for zip in [false, true] {
+            files.reverse();
+            assert!(build_files(&dir, &files, zip) == outputs);
+            files.swap(2, 6);
+            assert!(build_files(&dir, &files, zip) == outputs);
+        }
+    }
+
+    #[test]
+    fn check_dir_map_lumps() {
+        let dir = std::env::temp_dir().join(format!("d64make-check-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let light = crate::map::Light {
+            r: 255,
+            g: 128,
+            b: 0,
+            a: 255,
+            tag: 0,
+        };
+        let mut lights = Vec::new();
+        crate::map::write_lights_table(&[light], &mut lights).unwrap();
+        let action = |tag, special| crate::map::MacroAction {
+            batch: 0,
+            tag,
+            special,
+        };
+        let macros = crate::map::Macros {
+            specials: 1,
+            macros: vec![crate::map::Macro {
+                actions: vec![action(5, 1), action(0, 0)],
+            }],
+        };
+        let mut script = Vec::new();
+        macros.write_script(&mut script).unwrap();
+        // the maps these are for are in the base, which checking doesn't load
+        let files = [("LIGHTS/MAP05.TXT", lights), ("MACROS/MAP05.TXT", script)];
+        for (path, data) in &files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+        let mut checked = check_dir(&dir).unwrap();
+        checked.sort();
+        assert_eq!(checked, files.map(|(path, _)| dir.join(path)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_dir_skipped() {
+        let dir = std::env::temp_dir().join(format!("d64make-skip-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |path: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, [0; 4]).unwrap();
+        };
+        write("ENDOOM.LMP");
+        write(".notes.txt");
+        write(".git/objects/0123456789abcdef");
+        assert_eq!(check_dir(&dir).unwrap(), [dir.join("ENDOOM.LMP")]);
+        write("DEMOS/LONGDEMONAME.LMP");
+        let err = check_dir(&dir).unwrap_err().to_string();
+        assert!(err.contains("LONGDEMONAME"), "{err}");
+        assert!(!err.contains(".git"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refresh_manifest() {
+        let dir = std::env::temp_dir().join(format!("d64make-refresh-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("LONGNAME1.LMP"), [0; 4]).unwrap();
+        let paths = crate::extract::ReadPaths::default();
+        let errors = ErrorLog::new(false, None);
+        let options = LoadOptions {
+            filters: &paths.filters,
+            errors: &errors,
+            strict: false,
+            lock: None,
+            aliases: None,
+            map_table: None,
+            root: &dir,
+        };
+        let mut loaded = load_input(&dir, true, &paths, &options).unwrap();
+        let LoadedInput::Dir(_, files) = &loaded else {
+            unreachable!()
+        };
+        assert!(files[0].entry.is_none());
+        assert!(!refresh_input(&dir, &mut loaded, &options).unwrap());
+
+        let manifest = "[aliases]\nLONGNAME1 = SHORT\n";
+        std::fs::write(dir.join(project::MANIFEST_NAME), manifest).unwrap();
+        assert!(refresh_input(&dir, &mut loaded, &options).unwrap());
+        let LoadedInput::Dir(_, files) = &loaded else {
+            unreachable!()
+        };
+        assert!(
+            matches!(&files[0].entry, Some(LoadedEntry::Lump(entry, _)) if *entry == name("SHORT"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sources_added() {
+        let sprite = || {
+            let sprite = gfx::Sprite {
+                x_offset: 0,
+                y_offset: 0,
+                width: 0,
+                height: 0,
+                data: Vec::new(),
+                palette: gfx::SpritePalette::Rgb8(Box::new([Default::default(); 256])),
+            };
+            WadEntry::new(LumpType::Sprite, sprite)
+        };
+        let mut base = Wad::default();
+        base.sprites.insert(name("ZZZZA0"), sprite());
+        base.sprites.insert(name("POSSA1"), sprite());
+        let mut other = base.clone();
+        other.sprites.insert(name("XXXXA0"), sprite());
+        let inputs = ["base", "mod"].map(|path| Input::from(PathBuf::from(path)));
+        let loaded = [base, other].map(|wad| LoadedInput::Base(Box::new(wad), None));
+        let sources = Sources::new(&inputs, &loaded);
+        assert!(sources.added(LumpType::Sprite, &name("XXXXA0")));
+        // replaced by the second input
+        assert!(sources.added(LumpType::Sprite, &name("POSSA1")));
+        assert!(!Sources::new(&inputs[..1], &loaded[..1]).added(LumpType::Sprite, &name("ZZZZA0")));
+    }
+
+    #[test]
+    fn remaster_map_texture_hashes() {
+        let base = base_wad(&["?", "WALL", "DOOR"], &["FLOOR", "CEIL"]);
+        let names = base
+            .texture_names()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        let mut map = Map::default();
+        for (floor, ceiling) in [(3, 4), (4, 3)] {
+            map.sectors.push(crate::map::Sector {
+                floor,
+                ceiling,
+                ..Default::default()
+            });
+        }
+        for (top, bottom, middle) in [(1, 2, 0), (2, 1, 1)] {
+            map.sidedefs.push(crate::map::Sidedef {
+                top,
+                bottom,
+                middle,
+                ..Default::default()
+            });
+        }
+        let mut data = Vec::new();
+        crate::remaster::write_map(&map.to_wad("MAP01").unwrap(), &names, &mut data).unwrap();
+
+        let inputs = [
+            Input::from(PathBuf::from("base")),
+            Input::from(PathBuf::from("mod")),
+        ];
+        let files = vec![load("MAPS/MAP01.WAD", data, LumpType::Map)];
+        let loaded = [
+            LoadedInput::Base(Box::new(base), None),
+            LoadedInput::Dir(InputConfig::default(), files),
+        ];
+        let (mut iwad, _, _) = merge_inputs(&inputs, &loaded, &ErrorLog::new(false, None)).unwrap();
+        iwad.remap_map_textures().unwrap();
+        let built = Map::read(&iwad.maps[&name("MAP01")].data).unwrap();
+        assert_eq!(built.sectors, map.sectors);
+        assert_eq!(built.sidedefs, map.sidedefs);
+    }
+}
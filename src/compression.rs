@@ -5,3 +5,45 @@ mod vadpcm;
 pub use d64::*;
 pub use jaguar::*;
 pub use vadpcm::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::error::VerboseError;
+
+    fn inputs() -> Vec<Vec<u8>> {
+        let mut seed = 12345u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+        let incompressible = (0..20000).map(|_| random()).collect();
+        let pattern = (0..30000u32).map(|i| (i * 7 % 13 + i / 100 % 5) as u8);
+        vec![
+            Vec::new(),
+            vec![7],
+            b"hello hello hello hello".to_vec(),
+            incompressible,
+            vec![0; 70000],
+            pattern.collect(),
+        ]
+    }
+
+    #[test]
+    fn jaguar_round_trip() {
+        for input in inputs() {
+            let encoded = encode_jaguar(&input);
+            let (_, decoded) = decode_jaguar::<VerboseError<_>>(&encoded, input.len()).unwrap();
+            assert!(decoded == input, "{} bytes", input.len());
+        }
+    }
+
+    #[test]
+    fn d64_round_trip() {
+        for input in inputs() {
+            let encoded = encode_d64(&input);
+            let (_, decoded) = decode_d64::<VerboseError<_>>(&encoded, input.len()).unwrap();
+            assert!(decoded == input, "{} bytes", input.len());
+        }
+    }
+}
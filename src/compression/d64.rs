@@ -242,3 +242,129 @@ pub fn decode_d64<'a, E: ParseError<&'a [u8]>>(
     }
     Ok((decoder.input, output))
 }
+
+struct HuffmanEncoder {
+    output: Vec<u8>,
+    bit_count: u8,
+    bit_buffer: u8,
+    tables: HuffmanTables,
+    path: Vec<bool>,
+}
+
+impl HuffmanEncoder {
+    fn write_bit(&mut self, bit: bool) {
+        self.bit_buffer = (self.bit_buffer << 1) | bit as u8;
+        self.bit_count += 1;
+        if self.bit_count == 8 {
+            self.output.push(self.bit_buffer);
+            self.bit_buffer = 0;
+            self.bit_count = 0;
+        }
+    }
+    fn write_code(&mut self, value: u32, bits: u8) {
+        for i in 0..bits {
+            self.write_bit(value & (1 << i) != 0);
+        }
+    }
+    fn encode(&mut self, symbol: u16) {
+        let mut node = symbol as usize + 0x275;
+        self.path.clear();
+        while node != 1 {
+            let parent = self.tables.decode[INCR + node] as usize;
+            let bit = self.tables.decode[ODD + parent] as usize == node;
+            self.path.push(bit);
+            node = parent;
+        }
+        while let Some(bit) = self.path.pop() {
+            self.write_bit(bit);
+        }
+        self.tables.update(symbol as usize);
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.output.push(self.bit_buffer << (8 - self.bit_count));
+        }
+        self.output
+    }
+}
+
+/// Greedy LZ encoder with adaptive Huffman codes, producing data readable by [`decode_d64`]
+pub fn encode_d64(input: &[u8]) -> Vec<u8> {
+    const MIN_LEN: usize = 3;
+    const MAX_LEN: usize = 64;
+    const MAX_OFFSET: usize = 21840;
+    const MAX_CHAIN: usize = 256;
+    const HASH_BITS: u32 = 14;
+
+    #[inline]
+    fn hash(data: &[u8]) -> usize {
+        let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+        (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    let mut encoder = HuffmanEncoder {
+        output: Vec::with_capacity(input.len()),
+        bit_count: 0,
+        bit_buffer: 0,
+        tables: Default::default(),
+        path: Vec::new(),
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; input.len()];
+    let mut i = 0;
+    while i < input.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+        if i + MIN_LEN <= input.len() {
+            let max = MAX_LEN.min(input.len() - i);
+            let mut cand = head[hash(&input[i..])];
+            let mut chain = 0;
+            while cand != usize::MAX && i - cand < WINDOW_SIZE && chain < MAX_CHAIN {
+                let dist = i - cand;
+                // copies never overlap the bytes they produce
+                let max = max.min(dist);
+                let len = input[cand..cand + max]
+                    .iter()
+                    .zip(&input[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len >= MIN_LEN && dist - len < MAX_OFFSET && len > best_len {
+                    best_len = len;
+                    best_dist = dist;
+                    if len == MAX_LEN {
+                        break;
+                    }
+                }
+                cand = prev[cand];
+                chain += 1;
+            }
+        }
+        let step = if best_len >= MIN_LEN {
+            let offset = best_dist - best_len;
+            let shift_pos = OFFSET_TABLE[..6]
+                .iter()
+                .rposition(|o| *o <= offset)
+                .unwrap();
+            let symbol = 257 + shift_pos * 62 + (best_len - 3);
+            encoder.encode(symbol as u16);
+            encoder.write_code(
+                (offset - OFFSET_TABLE[shift_pos]) as u32,
+                (shift_pos * 2 + 4) as u8,
+            );
+            best_len
+        } else {
+            encoder.encode(input[i] as u16);
+            1
+        };
+        for j in i..i + step {
+            if j + MIN_LEN <= input.len() {
+                let h = hash(&input[j..]);
+                prev[j] = head[h];
+                head[h] = j;
+            }
+        }
+        i += step;
+    }
+    encoder.encode(256);
+    encoder.finish()
+}
@@ -57,3 +57,83 @@ pub fn decode_jaguar<'a, E: ParseError<&'a [u8]>>(
     }
     Ok((&[], output))
 }
+
+/// Greedy LZSS encoder producing data readable by [`decode_jaguar`]
+pub fn encode_jaguar(input: &[u8]) -> Vec<u8> {
+    const WINDOW: usize = 0x1000;
+    const MIN_LEN: usize = 3;
+    const MAX_LEN: usize = 16;
+    const MAX_CHAIN: usize = 256;
+    const HASH_BITS: u32 = 12;
+
+    #[inline]
+    fn hash(data: &[u8]) -> usize {
+        let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+        (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    let mut output = Vec::with_capacity(input.len() + input.len() / 8 + 3);
+    let mut id_pos = 0;
+    let mut id_bit = 8;
+    let mut push_id = |output: &mut Vec<u8>, set: bool| {
+        if id_bit == 8 {
+            id_pos = output.len();
+            output.push(0);
+            id_bit = 0;
+        }
+        if set {
+            output[id_pos] |= 1 << id_bit;
+        }
+        id_bit += 1;
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; input.len()];
+    let mut i = 0;
+    while i < input.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+        if i + MIN_LEN <= input.len() {
+            let max = MAX_LEN.min(input.len() - i);
+            let mut cand = head[hash(&input[i..])];
+            let mut chain = 0;
+            while cand != usize::MAX && i - cand <= WINDOW && chain < MAX_CHAIN {
+                let len = input[cand..]
+                    .iter()
+                    .zip(&input[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - cand;
+                    if len == max {
+                        break;
+                    }
+                }
+                cand = prev[cand];
+                chain += 1;
+            }
+        }
+        let step = if best_len >= MIN_LEN {
+            let pos = best_dist - 1;
+            push_id(&mut output, true);
+            output.push((pos >> 4) as u8);
+            output.push((((pos & 0xf) << 4) | (best_len - 1)) as u8);
+            best_len
+        } else {
+            push_id(&mut output, false);
+            output.push(input[i]);
+            1
+        };
+        for j in i..i + step {
+            if j + MIN_LEN <= input.len() {
+                let h = hash(&input[j..]);
+                prev[j] = head[h];
+                head[h] = j;
+            }
+        }
+        i += step;
+    }
+    push_id(&mut output, true);
+    output.extend_from_slice(&[0, 0]);
+    output
+}
@@ -0,0 +1,691 @@
+use crate::{
+    extract::WadType,
+    invalid_data,
+    map::{
+        read_records, Fixed, Light, Linedef, Map, Sector, Sidedef, Thing, Vertex, FIRST_LIGHT,
+        SECTOR_SECRET, THING_AMBUSH, THING_EASY, THING_HARD, THING_MULTI, THING_NORMAL,
+    },
+    project::MapTable,
+    EntryName, FlatEntry, FlatWad, LumpType,
+};
+use binrw::BinRead;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+};
+
+/// Lumps that can follow the marker of a map in a PC WAD
+const PC_LUMPS: &[&str] = &[
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT",
+    "BLOCKMAP", "BEHAVIOR", "SCRIPTS", "TEXTMAP", "ZNODES", "DIALOGUE", "ENDMAP",
+];
+
+/// Lumps that only Doom 64 maps have
+const N64_LUMPS: &[&str] = &["LEAFS", "LIGHTS", "MACROS"];
+
+/// Doom sector special that makes the sector a secret
+const DOOM_SECRET: i16 = 9;
+
+#[derive(BinRead)]
+#[br(little)]
+struct DoomThing {
+    x: i16,
+    y: i16,
+    angle: i16,
+    typ: i16,
+    flags: u16,
+}
+
+#[derive(BinRead)]
+#[br(little)]
+struct HexenThing {
+    tid: i16,
+    x: i16,
+    y: i16,
+    z: i16,
+    angle: i16,
+    typ: i16,
+    flags: u16,
+    _special: u8,
+    _args: [u8; 5],
+}
+
+const HEXEN_THING_SINGLE: u16 = 0x100;
+
+#[derive(BinRead)]
+#[br(little)]
+struct DoomLinedef {
+    v1: u16,
+    v2: u16,
+    flags: u16,
+    special: u16,
+    tag: i16,
+    sides: [u16; 2],
+}
+
+#[derive(BinRead)]
+#[br(little)]
+struct HexenLinedef {
+    v1: u16,
+    v2: u16,
+    flags: u16,
+    special: u8,
+    args: [u8; 5],
+    sides: [u16; 2],
+}
+
+#[derive(BinRead)]
+#[br(little)]
+struct PcSidedef {
+    x_offset: i16,
+    y_offset: i16,
+    top: [u8; 8],
+    bottom: [u8; 8],
+    middle: [u8; 8],
+    sector: u16,
+}
+
+#[derive(BinRead)]
+#[br(little)]
+struct PcVertex {
+    x: i16,
+    y: i16,
+}
+
+#[derive(BinRead)]
+#[br(little)]
+struct PcSector {
+    floor_height: i16,
+    ceiling_height: i16,
+    floor: [u8; 8],
+    ceiling: [u8; 8],
+    light: i16,
+    special: i16,
+    tag: i16,
+}
+
+/// Linedef flags that mean the same in Doom, Hexen and Doom 64
+const LINE_FLAGS: u32 = 0x1ff;
+/// Thing flags that mean the same in Doom and Doom 64
+const DOOM_THING_FLAGS: u16 = 0x1f;
+
+/// Builds a Doom 64 map out of the records of a PC one, collecting the textures and light
+/// levels it uses along the way
+struct Converter<'a> {
+    table: &'a MapTable,
+    /// Whether sector special 9 is the Doom secret special
+    doom_sectors: bool,
+    map: Map,
+    textures: Vec<(LumpType, EntryName)>,
+    texture_indices: HashMap<(LumpType, EntryName), u16>,
+    light_indices: HashMap<u8, u16>,
+    /// Specials missing from the table, reported once the map is converted
+    unmapped_specials: BTreeSet<u16>,
+    unmapped_sectors: BTreeSet<i16>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(table: &'a MapTable, doom_sectors: bool) -> Self {
+        Self {
+            table,
+            doom_sectors,
+            map: Map::default(),
+            textures: Vec::new(),
+            texture_indices: HashMap::new(),
+            light_indices: HashMap::new(),
+            unmapped_specials: BTreeSet::new(),
+            unmapped_sectors: BTreeSet::new(),
+        }
+    }
+    /// Index of a texture or flat in the list the map is recorded with. Missing textures
+    /// become `?`, the blank texture.
+    fn texture(&mut self, typ: LumpType, name: &str) -> io::Result<u16> {
+        let name = name.trim().to_ascii_uppercase();
+        let name = match self.table.textures.get(&name) {
+            Some(to) => to.clone(),
+            None if name.is_empty() || name == "-" => EntryName::new("?").unwrap(),
+            None => EntryName::new(&name).ok_or_else(|| {
+                invalid_data(format_args!("`{name}` is not a valid texture name"))
+            })?,
+        };
+        let key = (typ, name);
+        if let Some(&index) = self.texture_indices.get(&key) {
+            return Ok(index);
+        }
+        let index = self.textures.len() as u16;
+        self.textures.push(key.clone());
+        self.texture_indices.insert(key, index);
+        Ok(index)
+    }
+    /// Sector color of a grey light with the brightness of a PC light level
+    fn light(&mut self, level: i16) -> u16 {
+        let level = level.clamp(0, 255) as u8;
+        let lights = &mut self.map.lights;
+        *self.light_indices.entry(level).or_insert_with(|| {
+            lights.push(Light {
+                r: level,
+                g: level,
+                b: level,
+                a: 255,
+                tag: 0,
+            });
+            FIRST_LIGHT + lights.len() as u16 - 1
+        })
+    }
+    fn add_thing(&mut self, mut thing: Thing) {
+        if let Some(&typ) = self.table.things.get(&thing.typ) {
+            thing.typ = typ;
+        }
+        self.map.things.push(thing);
+    }
+    fn add_linedef(&mut self, mut line: Linedef) {
+        match self.table.specials.get(&line.special) {
+            Some(&special) => line.special = special,
+            None if line.special != 0 => {
+                self.unmapped_specials.insert(line.special);
+            }
+            None => {}
+        }
+        self.map.linedefs.push(line);
+    }
+    fn add_sidedef(
+        &mut self,
+        x_offset: i16,
+        y_offset: i16,
+        [top, bottom, middle]: [&str; 3],
+        sector: u16,
+    ) -> io::Result<()> {
+        let side = Sidedef {
+            x_offset,
+            y_offset,
+            top: self.texture(LumpType::Texture, top)?,
+            bottom: self.texture(LumpType::Texture, bottom)?,
+            middle: self.texture(LumpType::Texture, middle)?,
+            sector,
+        };
+        self.map.sidedefs.push(side);
+        Ok(())
+    }
+    fn add_sector(&mut self, sector: &PcSector, floor: &str, ceiling: &str) -> io::Result<()> {
+        let (special, flags) = match self.table.sectors.get(&sector.special) {
+            Some(&special) => (special, 0),
+            None if self.doom_sectors && sector.special == DOOM_SECRET => (0, SECTOR_SECRET),
+            None => {
+                if sector.special != 0 {
+                    self.unmapped_sectors.insert(sector.special);
+                }
+                (sector.special, 0)
+            }
+        };
+        let color = self.light(sector.light);
+        let sector = Sector {
+            floor_height: sector.floor_height,
+            ceiling_height: sector.ceiling_height,
+            floor: self.texture(LumpType::Flat, floor)?,
+            ceiling: self.texture(LumpType::Flat, ceiling)?,
+            colors: [color; 5],
+            special,
+            tag: sector.tag,
+            flags,
+        };
+        self.map.sectors.push(sector);
+        Ok(())
+    }
+    fn finish(mut self, name: &str) -> io::Result<(Map, Vec<(LumpType, EntryName)>)> {
+        fn list<T: std::fmt::Display>(numbers: &BTreeSet<T>) -> String {
+            numbers
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        if !self.unmapped_specials.is_empty() {
+            log::warn!(
+                "{name}: linedef specials {} are not in [specials] and were kept as they are",
+                list(&self.unmapped_specials)
+            );
+        }
+        if !self.unmapped_sectors.is_empty() {
+            log::warn!(
+                "{name}: sector specials {} are not in [sectors] and were kept as they are",
+                list(&self.unmapped_sectors)
+            );
+        }
+        crate::nodes::build_nodes(&mut self.map)
+            .map_err(|e| invalid_data(format_args!("{name}: {e}")))?;
+        Ok((self.map, self.textures))
+    }
+}
+
+/// Name stored in a fixed size field, up to the first NUL
+fn field_name(raw: &[u8; 8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+fn records<T>(lump: &str, data: &[u8]) -> io::Result<Vec<T>>
+where
+    T: BinRead + binrw::meta::ReadEndian,
+    for<'b> T::Args<'b>: Default,
+{
+    read_records(data).map_err(|_| invalid_data(format_args!("{lump}: truncated record")))
+}
+
+/// Reads the lumps of a map in the Doom or Hexen binary format
+fn convert_binary(
+    c: &mut Converter,
+    lumps: &HashMap<String, &[u8]>,
+    hexen: bool,
+) -> io::Result<()> {
+    let lump = |name: &str| lumps.get(name).copied().unwrap_or_default();
+    for v in records::<PcVertex>("VERTEXES", lump("VERTEXES"))? {
+        c.map.vertexes.push(Vertex {
+            x: Fixed::from_int(v.x),
+            y: Fixed::from_int(v.y),
+        });
+    }
+    for s in records::<PcSector>("SECTORS", lump("SECTORS"))? {
+        c.add_sector(&s, &field_name(&s.floor), &field_name(&s.ceiling))?;
+    }
+    for s in records::<PcSidedef>("SIDEDEFS", lump("SIDEDEFS"))? {
+        let textures = [&s.top, &s.bottom, &s.middle].map(field_name);
+        let textures = textures.each_ref().map(|t| t.as_str());
+        c.add_sidedef(s.x_offset, s.y_offset, textures, s.sector)?;
+    }
+    if hexen {
+        for l in records::<HexenLinedef>("LINEDEFS", lump("LINEDEFS"))? {
+            c.add_linedef(Linedef {
+                v1: l.v1,
+                v2: l.v2,
+                flags: l.flags as u32 & LINE_FLAGS,
+                special: l.special as u16,
+                // most Hexen specials take the sector tag as their first argument
+                tag: l.args[0] as i16,
+                sides: l.sides,
+            });
+        }
+        for t in records::<HexenThing>("THINGS", lump("THINGS"))? {
+            let mut flags = t.flags & (THING_EASY | THING_NORMAL | THING_HARD | THING_AMBUSH);
+            if t.flags & HEXEN_THING_SINGLE == 0 {
+                flags |= THING_MULTI;
+            }
+            c.add_thing(Thing {
+                x: t.x,
+                y: t.y,
+                z: t.z,
+                angle: t.angle,
+                typ: t.typ,
+                flags,
+                tid: t.tid,
+            });
+        }
+    } else {
+        for l in records::<DoomLinedef>("LINEDEFS", lump("LINEDEFS"))? {
+            c.add_linedef(Linedef {
+                v1: l.v1,
+                v2: l.v2,
+                flags: l.flags as u32 & LINE_FLAGS,
+                special: l.special,
+                tag: l.tag,
+                sides: l.sides,
+            });
+        }
+        for t in records::<DoomThing>("THINGS", lump("THINGS"))? {
+            c.add_thing(Thing {
+                x: t.x,
+                y: t.y,
+                z: 0,
+                angle: t.angle,
+                typ: t.typ,
+                flags: t.flags & DOOM_THING_FLAGS,
+                tid: 0,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Block of `key = value;` fields in a TEXTMAP lump
+struct UdmfBlock<'a> {
+    kind: String,
+    line: usize,
+    fields: HashMap<String, &'a str>,
+}
+
+impl UdmfBlock<'_> {
+    fn number<T: std::str::FromStr>(&self, key: &str, default: T) -> io::Result<T> {
+        match self.fields.get(key) {
+            Some(value) => value.parse().map_err(|_| {
+                invalid_data(format_args!(
+                    "TEXTMAP line {}: {} field `{key}` is not a number",
+                    self.line, self.kind
+                ))
+            }),
+            None => Ok(default),
+        }
+    }
+    fn required<T: std::str::FromStr + Default>(&self, key: &str) -> io::Result<T> {
+        if !self.fields.contains_key(key) {
+            return Err(invalid_data(format_args!(
+                "TEXTMAP line {}: {} has no `{key}`",
+                self.line, self.kind
+            )));
+        }
+        self.number(key, T::default())
+    }
+    fn flag(&self, key: &str) -> bool {
+        self.fields.get(key) == Some(&"true")
+    }
+    fn string<'s>(&'s self, key: &str) -> &'s str {
+        self.fields.get(key).copied().unwrap_or("-")
+    }
+    /// Float field rounded to the nearest map unit
+    fn coordinate(&self, key: &str) -> io::Result<i16> {
+        Ok(self.required::<f64>(key)?.round() as i16)
+    }
+}
+
+/// Splits a TEXTMAP lump into identifiers, values and punctuation, skipping comments
+fn udmf_tokens(text: &str) -> io::Result<Vec<(usize, &str)>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b'\n' => {
+                line += 1;
+                i += 1;
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let len = text[i + 2..].find("*/").ok_or_else(|| {
+                    invalid_data(format_args!("TEXTMAP line {line}: unterminated comment"))
+                })?;
+                line += text[i..i + 2 + len].matches('\n').count();
+                i += len + 4;
+            }
+            b'{' | b'}' | b'=' | b';' => {
+                tokens.push((line, &text[i..i + 1]));
+                i += 1;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                if i >= bytes.len() {
+                    return Err(invalid_data(format_args!(
+                        "TEXTMAP line {line}: unterminated string"
+                    )));
+                }
+                i += 1;
+                tokens.push((line, &text[start..i]));
+            }
+            _ => {
+                while i < bytes.len()
+                    && !bytes[i].is_ascii_whitespace()
+                    && !b"{}=;\"/".contains(&bytes[i])
+                {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(invalid_data(format_args!(
+                        "TEXTMAP line {line}: unexpected `{}`",
+                        bytes[i] as char
+                    )));
+                }
+                tokens.push((line, &text[start..i]));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Tokens of a TEXTMAP lump, keeping track of the line of the last one read
+struct UdmfTokens<'a> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<(usize, &'a str)>>,
+    line: usize,
+}
+
+impl<'a> UdmfTokens<'a> {
+    fn next(&mut self, what: &str) -> io::Result<&'a str> {
+        let (line, token) = self.tokens.next().ok_or_else(|| {
+            invalid_data(format_args!(
+                "TEXTMAP line {}: expected {what} before the end",
+                self.line
+            ))
+        })?;
+        self.line = line;
+        Ok(token)
+    }
+    fn expect(&mut self, token: &str, after: &str) -> io::Result<()> {
+        let found = self.next(&format!("`{token}`"))?;
+        if found != token {
+            return Err(invalid_data(format_args!(
+                "TEXTMAP line {}: expected `{token}` after `{after}`, found `{found}`",
+                self.line
+            )));
+        }
+        Ok(())
+    }
+    /// `= value;` after a key, with the quotes of strings removed
+    fn value(&mut self, key: &str) -> io::Result<&'a str> {
+        self.expect("=", key)?;
+        let value = self.next("a value")?;
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        self.expect(";", value)?;
+        Ok(value)
+    }
+}
+
+/// Reads a TEXTMAP lump into its namespace and blocks
+fn parse_udmf(text: &str) -> io::Result<(String, Vec<UdmfBlock<'_>>)> {
+    let mut tokens = UdmfTokens {
+        tokens: udmf_tokens(text)?.into_iter().peekable(),
+        line: 1,
+    };
+    let mut namespace = String::new();
+    let mut blocks = Vec::new();
+    while tokens.tokens.peek().is_some() {
+        let ident = tokens.next("a key")?;
+        if tokens.tokens.peek().map(|t| t.1) != Some("{") {
+            let value = tokens.value(ident)?;
+            if ident.eq_ignore_ascii_case("namespace") {
+                namespace = value.to_ascii_lowercase();
+            }
+            continue;
+        }
+        tokens.next("`{`")?;
+        let mut block = UdmfBlock {
+            kind: ident.to_ascii_lowercase(),
+            line: tokens.line,
+            fields: HashMap::new(),
+        };
+        loop {
+            let key = tokens.next("`}`")?;
+            if key == "}" {
+                break;
+            }
+            let value = tokens.value(key)?;
+            block.fields.insert(key.to_ascii_lowercase(), value);
+        }
+        blocks.push(block);
+    }
+    Ok((namespace, blocks))
+}
+
+/// Reads a map in the UDMF text format
+fn convert_udmf(c: &mut Converter, blocks: &[UdmfBlock], doom_namespace: bool) -> io::Result<()> {
+    const LINE_FLAG_NAMES: [&str; 9] = [
+        "blocking",
+        "blockmonsters",
+        "twosided",
+        "dontpegtop",
+        "dontpegbottom",
+        "secret",
+        "blocksound",
+        "dontdraw",
+        "mapped",
+    ];
+    for b in blocks {
+        match b.kind.as_str() {
+            "vertex" => c.map.vertexes.push(Vertex {
+                x: Fixed::from_f64(b.required("x")?),
+                y: Fixed::from_f64(b.required("y")?),
+            }),
+            "sector" => {
+                let sector = PcSector {
+                    floor_height: b.number("heightfloor", 0)?,
+                    ceiling_height: b.number("heightceiling", 0)?,
+                    floor: Default::default(),
+                    ceiling: Default::default(),
+                    light: b.number("lightlevel", 160)?,
+                    special: b.number("special", 0)?,
+                    tag: b.number("id", 0)?,
+                };
+                let secret = b.flag("secret");
+                c.add_sector(
+                    &sector,
+                    b.string("texturefloor"),
+                    b.string("textureceiling"),
+                )?;
+                if secret {
+                    c.map.sectors.last_mut().unwrap().flags |= SECTOR_SECRET;
+                }
+            }
+            "sidedef" => {
+                let textures =
+                    ["texturetop", "texturebottom", "texturemiddle"].map(|k| b.string(k));
+                let offsets = (b.number("offsetx", 0)?, b.number("offsety", 0)?);
+                c.add_sidedef(offsets.0, offsets.1, textures, b.required("sector")?)?;
+            }
+            "linedef" => {
+                let flags = LINE_FLAG_NAMES
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, name)| b.flag(name))
+                    .fold(0, |flags, (bit, _)| flags | 1 << bit);
+                let tag = match doom_namespace {
+                    true => b.number("id", 0i16)?.max(0),
+                    false => b.number("arg0", 0)?,
+                };
+                c.add_linedef(Linedef {
+                    v1: b.required("v1")?,
+                    v2: b.required("v2")?,
+                    flags,
+                    special: b.number("special", 0)?,
+                    tag,
+                    sides: [
+                        b.required("sidefront")?,
+                        b.number("sideback", -1i32)? as u16,
+                    ],
+                });
+            }
+            "thing" => {
+                let skill = |names: &[&str], flag: u16| match names.iter().any(|n| b.flag(n)) {
+                    true => flag,
+                    false => 0,
+                };
+                let mut flags = skill(&["skill1", "skill2"], THING_EASY)
+                    | skill(&["skill3"], THING_NORMAL)
+                    | skill(&["skill4", "skill5"], THING_HARD)
+                    | skill(&["ambush"], THING_AMBUSH);
+                if !b.flag("single") && (b.flag("coop") || b.flag("dm")) {
+                    flags |= THING_MULTI;
+                }
+                c.add_thing(Thing {
+                    x: b.coordinate("x")?,
+                    y: b.coordinate("y")?,
+                    z: b.number::<f64>("height", 0.)?.round() as i16,
+                    angle: b.number("angle", 0)?,
+                    typ: b.required("type")?,
+                    flags,
+                    tid: b.number("id", 0)?,
+                });
+            }
+            _ => log::debug!("Skipping TEXTMAP block `{}`", b.kind),
+        }
+    }
+    Ok(())
+}
+
+/// Map WAD and the textures and flats its texture indices refer to
+pub type ConvertedMap = (Vec<u8>, Vec<(LumpType, EntryName)>);
+
+/// Converts a map WAD made for PC Doom or Hexen, or written in UDMF, into a Doom 64 map WAD
+/// with a marker named `name`, built nodes and a light for each sector light level. Thing
+/// types, linedef specials, sector specials and texture names are replaced through `table`,
+/// and texture indices refer to the returned list of names until the build renumbers them.
+/// Returns `None` for maps already in a Doom 64 format.
+pub fn convert_map(data: &[u8], name: &str, table: &MapTable) -> io::Result<Option<ConvertedMap>> {
+    // maps that fail to parse are reported when they are merged
+    let Ok((_, wad)) = FlatWad::parse(data, WadType::N64Map, true, &Default::default()) else {
+        return Ok(None);
+    };
+    let is_lump = |e: &FlatEntry<Vec<u8>>, lumps: &[&str]| {
+        lumps.iter().any(|l| e.name.0.as_slice() == l.as_bytes())
+    };
+    if wad.entries.iter().any(|e| is_lump(e, N64_LUMPS)) {
+        return Ok(None);
+    }
+    // markers are the entries just before a map's first lump
+    let markers = wad
+        .entries
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| is_lump(&pair[1], &["THINGS", "TEXTMAP"]))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let Some(&first) = markers.first() else {
+        return Ok(None);
+    };
+    let start = markers
+        .iter()
+        .copied()
+        .find(|&i| wad.entries[i].name.display().eq_ignore_ascii_case(name))
+        .unwrap_or(first);
+    if markers.len() > 1 {
+        log::warn!(
+            "{name}: the WAD has {} maps, converting {}",
+            markers.len(),
+            wad.entries[start].name.display()
+        );
+    }
+    let lumps = wad.entries[start + 1..]
+        .iter()
+        .take_while(|e| is_lump(e, PC_LUMPS))
+        .map(|e| (e.name.display().into_owned(), e.entry.data.as_slice()))
+        .collect::<HashMap<_, _>>();
+    let (c, format) = if let Some(text) = lumps.get("TEXTMAP") {
+        let text = std::str::from_utf8(text).map_err(invalid_data)?;
+        let (namespace, blocks) =
+            parse_udmf(text).map_err(|e| invalid_data(format_args!("{name}: {e}")))?;
+        let doom = matches!(namespace.as_str(), "doom" | "heretic" | "strife");
+        let mut c = Converter::new(table, doom);
+        convert_udmf(&mut c, &blocks, doom)
+            .map_err(|e| invalid_data(format_args!("{name}: {e}")))?;
+        (c, "UDMF")
+    } else {
+        let hexen = lumps.contains_key("BEHAVIOR");
+        let mut c = Converter::new(table, !hexen);
+        convert_binary(&mut c, &lumps, hexen)
+            .map_err(|e| invalid_data(format_args!("{name}: {e}")))?;
+        (c, if hexen { "Hexen" } else { "Doom" })
+    };
+    log::info!("Converting {name} from the {format} map format");
+    let (map, textures) = c.finish(name)?;
+    let mut wad = map.to_wad(name)?;
+    wad.entries.push(FlatEntry::marker("ENDOFWAD"));
+    let mut data = Vec::new();
+    wad.write(&mut data, false)?;
+    Ok(Some((data, textures)))
+}
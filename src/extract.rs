@@ -4,12 +4,14 @@ use nom::error::{context, VerboseError};
 use std::{
     borrow::Cow,
     collections::BTreeMap,
-    io::{self, Read, Seek, Write},
+    fs::File,
+    io::{self, BufWriter, Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
 use crate::{
     convert_error, gfx, invalid_data,
+    lock::{Lock, LockEntry, LockFile, LockSound, LOCK_NAME},
     sound::{SampleData, SoundData},
     Compression, FlatEntry, FlatWad, LumpType, WadEntry,
 };
@@ -18,7 +20,7 @@ use crate::{
 pub struct Args {
     /// WAD or ROM file to extract
     input: PathBuf,
-    /// Directory to output WAD data into [default: DOOM64]
+    /// Directory to output WAD data into, or a `.pk3`/`.zip` archive [default: DOOM64]
     #[arg(short, long)]
     outdir: Option<PathBuf>,
     /// Extract only the first matched file to this path (ignores OUTDIR)
@@ -36,6 +38,20 @@ pub struct Args {
     /// Do not decompress WAD data
     #[arg(long, default_value_t = false)]
     no_decompress: bool,
+    /// Write maps as text that can be diffed and merged instead of map WADs
+    #[arg(long, default_value_t = false, conflicts_with_all = ["raw", "map_format"])]
+    map_text: bool,
+    /// Format to write maps in
+    #[arg(long, value_enum, default_value_t = MapFormat::N64, conflicts_with = "raw")]
+    map_format: MapFormat,
+    /// Also write the MACROS lump of each map as a script in MACROS/, which `build` compiles
+    /// back into the map
+    #[arg(long, default_value_t = false)]
+    macros: bool,
+    /// Also write the LIGHTS lump of each map to LIGHTS/ as a text table or a PNG strip of
+    /// swatches, which `build` reads back into the map
+    #[arg(long, value_enum)]
+    lights: Option<LightsFormat>,
     /// Optional WDD file to read when extracting IWAD [default: DOOM64.WDD]
     #[arg(long)]
     wdd: Option<PathBuf>,
@@ -50,6 +66,24 @@ pub struct Args {
     dls: Option<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum MapFormat {
+    /// Map WADs as they are stored in the IWAD
+    #[default]
+    N64,
+    /// Text, as written by `--map-text`
+    Text,
+    /// Uncompressed map WADs with texture fields holding hashes of the texture names, as
+    /// read by editors for the remaster
+    Remaster,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum LightsFormat {
+    Text,
+    Png,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Region {
     US,
@@ -173,22 +207,7 @@ impl WadType {
 }
 
 fn is_map_lump(name: &[u8]) -> bool {
-    [
-        b"THINGS".as_slice(),
-        b"LINEDEFS",
-        b"SIDEDEFS",
-        b"VERTEXES",
-        b"SEGS",
-        b"SSECTORS",
-        b"NODES",
-        b"SECTORS",
-        b"REJECT",
-        b"BLOCKMAP",
-        b"LEAFS",
-        b"LIGHTS",
-        b"MACROS",
-    ]
-    .contains(&name)
+    crate::map::LUMPS.iter().any(|l| l.as_bytes() == name)
 }
 
 impl FlatWad {
@@ -339,11 +358,11 @@ impl FlatWad {
                     .unwrap_or_else(|| table_offset - offset as usize);
                 match (compression, wad_type.is_prototype(), decompress) {
                     (Compression::None, _, _) => wad[start..start + size as usize].to_owned(),
-                    (_, _, false) => wad[start..start + compressed_size].to_owned(),
-                    (Compression::Huffman(_), true, true) => {
+                    (Compression::Huffman(_), true, _) => {
                         compression = Compression::Lzss(size as usize);
                         wad[start..start + compressed_size].to_owned()
                     }
+                    (_, _, false) => wad[start..start + compressed_size].to_owned(),
                     (Compression::Lzss(_), _, true) => {
                         compression = Compression::None;
                         context("Jag Decompression", |d| {
@@ -521,6 +540,14 @@ impl FlatWad {
     }
 }
 
+/// Converts a map WAD entry to the text format read by `build`
+fn map_to_text(entry: &WadEntry<Vec<u8>>) -> io::Result<Vec<u8>> {
+    let wad = crate::map::read_map_wad(entry)?;
+    let mut text = Vec::new();
+    crate::map::Map::read(&wad)?.write_text(&wad, &mut text)?;
+    Ok(text)
+}
+
 #[inline]
 fn read_rom_data(data: &[u8], offset: u32, size: u32) -> &[u8] {
     &data[offset as usize..(offset + size) as usize]
@@ -712,6 +739,9 @@ pub fn extract(mut args: Args) -> io::Result<()> {
     use LumpType::*;
 
     args.outdir = Some(args.outdir.unwrap_or_else(|| PathBuf::from("DOOM64")));
+    if args.map_text {
+        args.map_format = MapFormat::Text;
+    }
     let outdir = args.outdir.as_deref().unwrap();
 
     let paths = ReadPaths {
@@ -731,9 +761,59 @@ pub fn extract(mut args: Args) -> io::Result<()> {
     let (wad, snd) = read_rom_or_iwad(&args.input, flags, &paths)?;
     let wad = wad.unwrap();
 
+    let mut lock = Lock::default();
+    if args.outfile.is_none() {
+        // decompressed entries no longer know how they were stored, so read the table as-is
+        let stored_paths = ReadPaths {
+            filters: Default::default(),
+            wdd: None,
+            wmd: None,
+            wsd: None,
+            dls: None,
+        };
+        let (stored, _) = read_rom_or_iwad(&args.input, ReadFlags::IWAD, &stored_paths)?;
+        lock.entries = stored
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|FlatEntry { name, entry }| LockEntry {
+                name,
+                typ: entry.typ,
+                compression: entry.compression,
+            })
+            .collect();
+    }
+
+    let textures = match args.map_format {
+        MapFormat::Remaster => wad
+            .texture_names()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect(),
+        _ => Vec::new(),
+    };
+
     let mut palettes = PaletteCache::default();
+    let mut output = Output {
+        dir: outdir.to_owned(),
+        archive: None,
+        aliases: BTreeMap::new(),
+    };
     if args.outfile.is_none() {
-        std::fs::create_dir_all(outdir).unwrap();
+        if is_archive_path(outdir) {
+            log::info!("Writing `{}`", outdir.display());
+            let file = BufWriter::new(File::create(outdir)?);
+            output.archive = Some(zip::ZipWriter::new(file));
+        } else {
+            std::fs::create_dir_all(outdir).unwrap();
+            if let Some(manifest) = crate::project::Manifest::read(outdir)? {
+                output.aliases = manifest
+                    .aliases
+                    .into_iter()
+                    .map(|(file, name)| (name.display().into_owned(), file))
+                    .collect();
+            }
+        }
     }
     fn ext_for(typ: LumpType) -> &'static str {
         match typ {
@@ -763,22 +843,89 @@ pub fn extract(mut args: Args) -> io::Result<()> {
         if entry.typ == Marker {
             continue;
         }
-        if let Some(mut file) = args.try_create_file(
+        let map_format = match entry.typ {
+            Map => args.map_format,
+            _ => MapFormat::N64,
+        };
+        if let Some(mut file) = output.try_create_file(
+            &args,
             || subdir_for(entry.typ),
             || name.display(),
-            || ext_for(entry.typ),
+            || match map_format {
+                MapFormat::Text => "TXT",
+                _ => ext_for(entry.typ),
+            },
             entry.compression.is_compressed().then_some("LMPZ"),
         )? {
-            let data = wad.extract_one(index, &mut palettes, args.raw)?;
+            let map_error =
+                |e| invalid_data(format_args!("Failed to read map {}: {e}", name.display()));
+            let data = match map_format {
+                MapFormat::N64 => wad.extract_one(index, &mut palettes, args.raw)?,
+                MapFormat::Text => Cow::Owned(map_to_text(entry).map_err(map_error)?),
+                MapFormat::Remaster => {
+                    let mut data = Vec::new();
+                    crate::remaster::write_map(
+                        &crate::map::read_map_wad(entry).map_err(map_error)?,
+                        &textures,
+                        &mut data,
+                    )
+                    .map_err(map_error)?;
+                    Cow::Owned(data)
+                }
+            };
             file.write_all(&data).unwrap();
+            lock.add_file(&mut output, file, entry.typ)?;
         }
         if args.outfile.is_some() {
             break;
         }
+        if entry.typ == Map && (args.macros || args.lights.is_some()) {
+            let map = crate::map::read_map_wad(entry)
+                .and_then(|wad| crate::map::Map::read(&wad))
+                .map_err(|e| {
+                    invalid_data(format_args!("Failed to read map {}: {e}", name.display()))
+                })?;
+            if let (true, Some(macros)) = (args.macros, &map.macros) {
+                if let Some(mut file) = output.try_create_file(
+                    &args,
+                    || Some("MACROS"),
+                    || name.display(),
+                    || "TXT",
+                    None,
+                )? {
+                    macros.write_script(&mut file)?;
+                    lock.add_file(&mut output, file, MapLump)?;
+                }
+            }
+            if let (Some(format), false) = (args.lights, map.lights.is_empty()) {
+                let ext = match format {
+                    LightsFormat::Text => "TXT",
+                    LightsFormat::Png => "PNG",
+                };
+                if let Some(mut file) = output.try_create_file(
+                    &args,
+                    || Some("LIGHTS"),
+                    || name.display(),
+                    || ext,
+                    None,
+                )? {
+                    match format {
+                        LightsFormat::Text => {
+                            crate::map::write_lights_table(&map.lights, &mut file)?
+                        }
+                        LightsFormat::Png => {
+                            file.write_all(&crate::map::write_lights_png(&map.lights)?)?
+                        }
+                    }
+                    lock.add_file(&mut output, file, MapLump)?;
+                }
+            }
+        }
     }
     if let Some(snd) = snd {
         if !snd.instruments.is_empty() {
-            if let Some(mut file) = args.try_create_file(
+            if let Some(mut file) = output.try_create_file(
+                &args,
                 || Some("MUSIC"),
                 || Cow::Borrowed("DOOMSND"),
                 || "SF2",
@@ -789,12 +936,14 @@ pub fn extract(mut args: Args) -> io::Result<()> {
                 } else {
                     snd.write_sf2(&mut file).unwrap();
                 }
+                lock.add_file(&mut output, file, SoundFont)?;
             }
         }
         for (index, seq) in &snd.sequences {
             match seq {
                 crate::sound::Sequence::MusicSeq(seq) => {
-                    if let Some(mut file) = args.try_create_file(
+                    if let Some(mut file) = output.try_create_file(
+                        &args,
                         || Some("MUSIC"),
                         || Cow::Owned(format!("MUS_{index:03}")),
                         || "MID",
@@ -805,10 +954,20 @@ pub fn extract(mut args: Args) -> io::Result<()> {
                         } else {
                             seq.write_midi(&snd, &mut file).unwrap();
                         }
+                        lock.add_file(&mut output, file, Sequence)?;
                     }
                 }
                 crate::sound::Sequence::Effect(sample) => {
-                    if let Some(mut file) = args.try_create_file(
+                    lock.sounds.insert(
+                        *index,
+                        LockSound {
+                            priority: sample.priority,
+                            volume: sample.volume,
+                            r#loop: sample.info.r#loop.clone(),
+                        },
+                    );
+                    if let Some(mut file) = output.try_create_file(
+                        &args,
                         || Some("SOUNDS"),
                         || Cow::Owned(format!("SFX_{index:03}")),
                         || "WAV",
@@ -836,68 +995,250 @@ pub fn extract(mut args: Args) -> io::Result<()> {
                         } else {
                             sample.write_wav(&mut file)?;
                         }
+                        lock.add_file(&mut output, file, Sample)?;
+                    }
+                }
+                crate::sound::Sequence::MusicSample(sample) => {
+                    let sample = sample.to_sample();
+                    lock.sounds.insert(
+                        *index,
+                        LockSound {
+                            priority: sample.priority,
+                            volume: sample.volume,
+                            r#loop: sample.info.r#loop.clone(),
+                        },
+                    );
+                    if let Some(mut file) = output.try_create_file(
+                        &args,
+                        || Some("MUSIC"),
+                        || Cow::Owned(format!("MUS_{index:03}")),
+                        || "WAV",
+                        Some("WAV"),
+                    )? {
+                        sample.write_wav(&mut file)?;
+                        lock.add_file(&mut output, file, Sequence)?;
                     }
                 }
-                crate::sound::Sequence::MusicSample(_) => unreachable!(),
             }
             if args.outfile.is_some() {
                 break;
             }
         }
     }
+    if args.outfile.is_none() {
+        for (sprite, source) in &palettes.sprite_to_palette {
+            lock.palettes.insert(
+                wad.entries[*sprite].name.clone(),
+                wad.entries[*source].name.clone(),
+            );
+        }
+        let mut file = output.create_file(LOCK_NAME.to_owned())?;
+        lock.write(&mut file)?;
+        output.finish(file)?;
+    }
+    if let Some(mut archive) = output.archive {
+        archive.finish().map_err(invalid_data)?.flush()?;
+    }
     Ok(())
 }
 
+/// Returns `true` if the path names a ZIP/PK3 archive rather than a directory
+pub(crate) fn is_archive_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pk3") || e.eq_ignore_ascii_case("zip"))
+}
+
+/// Where extracted files are written, owned by [`extract`] apart from its arguments
+struct Output {
+    dir: PathBuf,
+    archive: Option<zip::ZipWriter<BufWriter<File>>>,
+    /// Entry names mapped to the longer file names given to them in the project manifest
+    aliases: BTreeMap<String, String>,
+}
+
+/// Output file of an extracted entry, either on disk or inside an archive
+struct OutFile {
+    /// Path relative to the output directory or archive root
+    path: String,
+    hasher: blake3::Hasher,
+    writer: OutWriter,
+}
+
+enum OutWriter {
+    File(BufWriter<File>),
+    /// Contents of an archive entry, added to the archive by [`Output::finish`] so that
+    /// several files can be open at once
+    Archive(Vec<u8>),
+}
+
+impl Write for OutFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = match &mut self.writer {
+            OutWriter::File(f) => f.write(buf)?,
+            OutWriter::Archive(a) => a.write(buf)?,
+        };
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            OutWriter::File(f) => f.flush(),
+            OutWriter::Archive(_) => Ok(()),
+        }
+    }
+}
+
+impl Lock {
+    /// Finishes a fully written file and records its hash
+    fn add_file(&mut self, output: &mut Output, file: OutFile, typ: LumpType) -> io::Result<()> {
+        let (path, hash) = output.finish(file)?;
+        self.files.insert(path, LockFile { typ, hash });
+        Ok(())
+    }
+}
+
 impl Args {
+    /// Arguments to extract everything from `input` into `outdir`, for other commands
+    pub(crate) fn new(input: PathBuf, outdir: PathBuf, paths: ReadPaths) -> Self {
+        Self {
+            input,
+            outdir: Some(outdir),
+            outfile: None,
+            include: paths.filters.includes,
+            flat: false,
+            raw: false,
+            no_decompress: false,
+            map_text: false,
+            map_format: MapFormat::N64,
+            macros: false,
+            lights: None,
+            wdd: paths.wdd,
+            wmd: paths.wmd,
+            wsd: paths.wsd,
+            dls: paths.dls,
+        }
+    }
+}
+
+impl Output {
     fn try_create_file<'a>(
         &self,
+        args: &Args,
         mk_subdir: impl FnOnce() -> Option<&'a str>,
         mk_filename: impl FnOnce() -> Cow<'a, str>,
         mk_ext: impl FnOnce() -> &'a str,
         raw_ext: Option<&str>,
-    ) -> std::io::Result<Option<impl std::io::Write>> {
-        let Self {
-            outdir,
+    ) -> std::io::Result<Option<OutFile>> {
+        let Args {
             outfile,
             include,
             flat,
             raw,
             ..
-        } = self;
-        let outdir = outdir.as_deref().unwrap();
-        let filename = if let Some(outfile) = &outfile {
-            Cow::Borrowed(outfile)
+        } = args;
+        if let Some(outfile) = &outfile {
+            log::debug!("writing `{}`", outfile.display());
+            let writer = File::create(outfile).map(BufWriter::new)?;
+            return Ok(Some(OutFile {
+                path: outfile.display().to_string(),
+                hasher: blake3::Hasher::new(),
+                writer: OutWriter::File(writer),
+            }));
+        }
+        let name = mk_filename();
+        if !include.is_empty() && !include.iter().any(|g| glob_match::glob_match(g, &name)) {
+            return Ok(None);
+        }
+        let filename = match self.aliases.get(name.as_ref()) {
+            Some(alias) => alias.clone(),
+            None => crate::encode_file_name(name.as_bytes()),
+        };
+        let subdir = (!flat).then_some(mk_subdir()).flatten();
+        let ext = match raw {
+            true => raw_ext.unwrap_or("LMP"),
+            false => mk_ext(),
+        };
+        let path = match subdir {
+            Some(subdir) => format!("{subdir}/{filename}.{ext}"),
+            None => format!("{filename}.{ext}"),
+        };
+        self.create_file(path).map(Some)
+    }
+    /// Creates a file at a `/`-separated path inside the output directory or archive
+    fn create_file(&self, path: String) -> io::Result<OutFile> {
+        let writer = if self.archive.is_some() {
+            log::debug!("writing `{path}`");
+            OutWriter::Archive(Vec::new())
         } else {
-            #[allow(unused_mut)]
-            let mut filename = mk_filename();
-            #[cfg(windows)]
-            {
-                filename = Cow::Owned(filename.replace('\\', "^").replace('?', "@"));
+            let filename = self.dir.join(&path);
+            if let Some(dir) = filename.parent() {
+                std::fs::create_dir_all(dir)?;
             }
-            if !include.is_empty() && !include.iter().any(|g| glob_match::glob_match(g, &filename))
-            {
-                return Ok(None);
-            }
-            let filename = Path::new(filename.as_ref());
-            let dir = match (!flat).then_some(mk_subdir()).flatten() {
-                Some(subdir) => {
-                    let dir = outdir.join(Path::new(subdir));
-                    std::fs::create_dir_all(&dir).unwrap();
-                    Cow::Owned(dir)
-                }
-                None => Cow::Borrowed(outdir),
-            };
-            let mut filename = dir.join(filename);
-            if *raw {
-                filename.set_extension(raw_ext.unwrap_or("LMP"));
-            } else {
-                filename.set_extension(mk_ext());
+            log::debug!("writing `{}`", filename.display());
+            OutWriter::File(File::create(&filename).map(BufWriter::new)?)
+        };
+        Ok(OutFile {
+            path,
+            hasher: blake3::Hasher::new(),
+            writer,
+        })
+    }
+    /// Flushes a fully written file, or adds it to the archive, and returns its path and hash
+    fn finish(&mut self, file: OutFile) -> io::Result<(String, blake3::Hash)> {
+        let OutFile {
+            path,
+            hasher,
+            writer,
+        } = file;
+        match writer {
+            OutWriter::File(mut f) => f.flush()?,
+            OutWriter::Archive(data) => {
+                let archive = self.archive.as_mut().unwrap();
+                archive
+                    .start_file(path.as_str(), zip::write::FileOptions::default())
+                    .map_err(invalid_data)?;
+                archive.write_all(&data)?;
             }
-            Cow::Owned(filename)
+        }
+        Ok((path, hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_files_open_at_once() {
+        let path = std::env::temp_dir().join(format!("d64make-extract-{}.pk3", std::process::id()));
+        let mut output = Output {
+            dir: path.clone(),
+            archive: Some(zip::ZipWriter::new(BufWriter::new(
+                File::create(&path).unwrap(),
+            ))),
+            aliases: BTreeMap::new(),
         };
-        log::debug!("writing `{}`", filename.display());
-        std::fs::File::create(filename.as_path())
-            .map(std::io::BufWriter::new)
-            .map(Some)
+        let mut a = output.create_file("A.LMP".to_owned()).unwrap();
+        let mut b = output.create_file("SUB/B.LMP".to_owned()).unwrap();
+        a.write_all(b"first").unwrap();
+        b.write_all(b"second").unwrap();
+        let mut lock = Lock::default();
+        lock.add_file(&mut output, b, LumpType::Unknown).unwrap();
+        lock.add_file(&mut output, a, LumpType::Unknown).unwrap();
+        output.archive.unwrap().finish().unwrap().flush().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        for (name, data) in [("A.LMP", "first"), ("SUB/B.LMP", "second")] {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, data);
+            assert!(lock.is_unchanged(name, data.as_bytes()));
+        }
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -69,6 +69,73 @@ pub fn palette_rgb_to_16(input: &[u8], output: &mut [u8]) {
     }
 }
 
+/// Width and height in pixels of each color in a swatch strip
+const SWATCH_SIZE: usize = 16;
+
+/// Writes colors as a row of opaque square swatches, storing `tags` as big-endian words in
+/// an `ltAg` chunk and the alpha of each color in an `ltAl` chunk
+pub fn write_swatch_png(colors: &[RGBA], tags: &[i16]) -> io::Result<Vec<u8>> {
+    let mut encoder = lodepng::Encoder::new();
+    let tags = tags
+        .iter()
+        .flat_map(|t| t.to_be_bytes())
+        .collect::<Vec<_>>();
+    let alphas = colors.iter().map(|c| c.a).collect::<Vec<_>>();
+    let info = encoder.info_png_mut();
+    info.create_chunk(lodepng::ChunkPosition::IHDR, b"ltAg", &tags)
+        .map_err(invalid_data)?;
+    info.create_chunk(lodepng::ChunkPosition::IHDR, b"ltAl", &alphas)
+        .map_err(invalid_data)?;
+    let width = colors.len() * SWATCH_SIZE;
+    let mut pixels = vec![RGBA::default(); width * SWATCH_SIZE];
+    for row in pixels.chunks_exact_mut(width) {
+        for (swatch, color) in row.chunks_exact_mut(SWATCH_SIZE).zip(colors) {
+            swatch.fill(RGBA { a: 0xff, ..*color });
+        }
+    }
+    encoder
+        .encode(&pixels, width, SWATCH_SIZE)
+        .map_err(invalid_data)
+}
+
+/// Reads the center pixel of each swatch in a strip written by [`write_swatch_png`], along
+/// with the tags stored with them. Tags missing from the PNG are zero, and alphas missing
+/// from it are taken from the pixels.
+pub fn read_swatch_png(data: &[u8]) -> io::Result<(Vec<RGBA>, Vec<i16>)> {
+    let mut decoder = lodepng::Decoder::new();
+    decoder.remember_unknown_chunks(true);
+    let bitmap = match decoder.decode(data).map_err(invalid_data)? {
+        lodepng::Image::RGBA(b) => b,
+        _ => return Err(invalid_data("Swatch PNG could not be converted to RGBA")),
+    };
+    let size = bitmap.height;
+    if size == 0 || bitmap.width % size != 0 {
+        return Err(invalid_data("Swatch PNG must be a row of square swatches"));
+    }
+    let center = (size / 2) * bitmap.width + size / 2;
+    let mut colors = (0..bitmap.width / size)
+        .map(|i| bitmap.buffer[center + i * size])
+        .collect::<Vec<_>>();
+    let info = decoder.info_png();
+    if let Some(alphas) = info.get(b"ltAl") {
+        for (color, a) in colors.iter_mut().zip(alphas.data()) {
+            color.a = *a;
+        }
+    }
+    let mut tags = info
+        .get(b"ltAg")
+        .map(|chunk| {
+            chunk
+                .data()
+                .chunks_exact(2)
+                .map(|t| i16::from_be_bytes([t[0], t[1]]))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    tags.resize(colors.len(), 0);
+    Ok((colors, tags))
+}
+
 const CELL_SIZE: usize = 8;
 
 fn unpack_pixels(n64: &[u8], width: u16, height: u16, rgb8: bool) -> Option<Vec<u8>> {
@@ -493,6 +560,18 @@ impl Texture {
         }
         Ok(())
     }
+    /// Color of a pixel in the first palette, wrapping coordinates around the texture size
+    pub fn pixel(&self, x: i32, y: i32) -> RGBA {
+        let width = 1i32 << self.wshift;
+        let height = 1i32 << self.hshift;
+        let (x, y) = ((x & (width - 1)) as usize, (y & (height - 1)) as usize);
+        let byte = self.data[y * (width as usize / 2) + x / 2];
+        let index = if x & 1 == 0 { byte >> 4 } else { byte & 0xf };
+        self.palettes
+            .first()
+            .map(|p| p[index as usize])
+            .unwrap_or_default()
+    }
     pub fn to_vec(&self) -> Vec<u8> {
         let imgsize = 1 << ((self.wshift + self.hshift + 31) & 31);
         let palsize = self.palettes.len() * 2 * 16;
@@ -0,0 +1,295 @@
+use crate::{
+    extract::{read_rom_or_iwad, ReadFlags, ReadPaths},
+    gfx,
+    project::{Manifest, MANIFEST_NAME, PROJECT_DIRS},
+    sound::{PatchInfo, Sample, SampleData, SoundData},
+};
+use std::{
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Directory to create the project in
+    dir: PathBuf,
+    /// ROM or IWAD the project will be built on top of
+    #[arg(short, long)]
+    base: PathBuf,
+    /// Optional WDD file to read when the base is an IWAD [default: DOOM64.WDD]
+    #[arg(long)]
+    wdd: Option<PathBuf>,
+    /// Optional WMD file to read when the base is an IWAD [default: DOOM64.WMD]
+    #[arg(long)]
+    wmd: Option<PathBuf>,
+    /// Optional WSD file to read when the base is an IWAD [default: DOOM64.WSD]
+    #[arg(long)]
+    wsd: Option<PathBuf>,
+    /// Optional DLS file to read when the base is a remaster IWAD [default: DOOMSND.DLS]
+    #[arg(long)]
+    dls: Option<PathBuf>,
+}
+
+const SAMPLE_SPRITE: &str = "SMPLA0";
+
+fn create_new(path: &Path) -> io::Result<io::BufWriter<std::fs::File>> {
+    log::debug!("writing `{}`", path.display());
+    std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map(io::BufWriter::new)
+}
+
+/// 32x32 8-bit sprite with a transparent border, origin at the bottom center
+fn sample_sprite() -> gfx::Sprite {
+    const SIZE: u16 = 32;
+    let mut palette = Box::new([gfx::RGBA::default(); 256]);
+    for (i, c) in palette.iter_mut().enumerate().skip(1) {
+        *c = gfx::RGBA::new(i as u8, 255 - i as u8, 128, 255);
+    }
+    let mut data = vec![0u8; SIZE as usize * SIZE as usize];
+    for y in 4..SIZE - 4 {
+        for x in 4..SIZE - 4 {
+            data[(y * SIZE + x) as usize] = (1 + x * 8) as u8;
+        }
+    }
+    gfx::Sprite {
+        x_offset: (SIZE / 2) as i16,
+        y_offset: SIZE as i16,
+        width: SIZE,
+        height: SIZE,
+        data,
+        palette: gfx::SpritePalette::Rgb8(palette),
+    }
+}
+
+/// Quarter second 440 Hz tone at the native sample rate
+fn sample_sound() -> Sample {
+    const RATE: u32 = 22050;
+    let samples = (0..RATE / 4)
+        .map(|i| {
+            let t = i as f64 / RATE as f64;
+            ((t * 440.0 * std::f64::consts::TAU).sin() * 8192.0) as i16
+        })
+        .collect();
+    Sample {
+        info: PatchInfo {
+            samples: SampleData::Raw(samples),
+            pitch: crate::sound::samplerate_to_cents(RATE),
+            r#loop: None,
+        },
+        priority: 50,
+        volume: 127,
+    }
+}
+
+/// Path that leads from `dir` to `path`, both of them absolute
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // on another drive
+    if common == 0 {
+        return path.to_owned();
+    }
+    let up = dir.components().skip(common).map(|_| Component::ParentDir);
+    up.chain(path.components().skip(common)).collect()
+}
+
+fn folder_notes(dir: &str) -> &'static str {
+    match dir {
+        "FLATS" | "TEXTURES" => "4-bit indexed color, power-of-two size",
+        "GRAPHICS" => "Menu graphics and font",
+        "HUD" => "HUD graphics and font",
+        "LIGHTS" => "Light tables for maps",
+        "MACROS" => "Macro scripts for maps",
+        "MAPS" => "N64 or PC map WADs, or text maps",
+        "MUSIC" => "`MUS_###` sequences and soundfonts",
+        "PALETTES" => "Sprite palettes (RGB8, 256 entries)",
+        "SKIES" => "Sky textures",
+        "SOUNDS" => "`SFX_###` sequences",
+        "SPRITES" => "8-bit or 4-bit indexed color",
+        _ => "",
+    }
+}
+
+/// Markdown table of the folders in [`PROJECT_DIRS`] with the formats `build --strict`
+/// accepts in each
+fn folder_table() -> String {
+    let mut dirs = PROJECT_DIRS.to_vec();
+    dirs.sort_unstable();
+    let rows = dirs
+        .into_iter()
+        .map(|dir| {
+            let typ = crate::build::type_for_dir(dir.as_ref()).unwrap();
+            let formats = crate::build::allowed_extensions(typ, Some(dir)).join(", ");
+            (dir, formats, folder_notes(dir))
+        })
+        .collect::<Vec<_>>();
+    let formats_width = rows.iter().map(|r| r.1.len()).max().unwrap_or_default();
+    let notes_width = rows.iter().map(|r| r.2.len()).max().unwrap_or_default();
+    let mut table = String::from("| Folder | Formats | Notes |\n|-:|-|-|\n");
+    for (dir, formats, notes) in rows {
+        table += &format!("| {dir:<8} | {formats:<formats_width$} | {notes:<notes_width$} |\n");
+    }
+    table
+}
+
+fn write_readme(w: &mut impl Write, name: &str, snd: &SoundData) -> io::Result<()> {
+    let next_seq = snd
+        .sequences
+        .last_key_value()
+        .map(|e| *e.0 + 1)
+        .unwrap_or_default();
+    let next_patch = snd
+        .instruments
+        .last_key_value()
+        .map(|e| *e.0 + 1)
+        .unwrap_or_default();
+    let program = next_patch & 0x7f;
+    let bank = next_patch >> 7;
+    let table = folder_table();
+    write!(
+        w,
+        "\
+# {name}
+
+Build with `d64make build . -o DOOM64.WAD`. The base ROM or IWAD is read from
+`{MANIFEST_NAME}`.
+
+## Layout
+
+{table}
+File names without the extension become lump names and must be at most 8
+characters long. A file with the same name as a base game resource replaces it.
+
+## Sounds and music
+
+Effects and music share one sequence number space. Name new effects
+`SFX_###.WAV` and new songs `MUS_###.MID` using numbers that are not used by
+the base game.
+
+- Next free sequence number: {next_seq}
+- Next free MIDI instrument: program {program}, bank {bank}
+
+WAV files must be uncompressed 16-bit or 8-bit mono. `SOUNDS/SFX_{next_seq:03}.WAV`
+is an example effect and `SPRITES/{SAMPLE_SPRITE}.PNG` is an example 8-bit
+indexed sprite with a `grAb` offset chunk. Replace or delete them before
+releasing the project.
+"
+    )?;
+    Ok(())
+}
+
+pub fn init(args: Args) -> io::Result<()> {
+    let Args {
+        dir,
+        base,
+        wdd,
+        wmd,
+        wsd,
+        dls,
+    } = args;
+    if dir.join(MANIFEST_NAME).exists() {
+        return Err(crate::invalid_data(format_args!(
+            "`{}` already contains a project",
+            dir.display()
+        )));
+    }
+    let paths = ReadPaths {
+        wdd,
+        wmd,
+        wsd,
+        dls,
+        ..Default::default()
+    };
+    let (_, snd) = read_rom_or_iwad(&base, ReadFlags::IWAD | ReadFlags::SOUND, &paths)?;
+    let snd = snd.unwrap_or_default();
+
+    log::info!("Creating project in `{}`", dir.display());
+    for subdir in PROJECT_DIRS {
+        std::fs::create_dir_all(dir.join(subdir))?;
+    }
+    let full_dir = std::fs::canonicalize(&dir)?;
+    let name = full_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("mod"));
+    // relative paths are kept working when the project is built from another directory
+    let base = if base.is_absolute() {
+        base
+    } else {
+        relative_path(&std::fs::canonicalize(&base)?, &full_dir)
+    };
+    let manifest = Manifest {
+        name,
+        base: Some(base),
+        ..Default::default()
+    };
+    let mut file = create_new(&dir.join(MANIFEST_NAME))?;
+    manifest.write(&mut file)?;
+    file.flush()?;
+
+    let mut file = create_new(&dir.join("README.md"))?;
+    write_readme(&mut file, &manifest.name, &snd)?;
+    file.flush()?;
+
+    let png = sample_sprite()
+        .write_png(None)
+        .map_err(crate::invalid_data)?;
+    let mut file = create_new(&dir.join("SPRITES").join(format!("{SAMPLE_SPRITE}.PNG")))?;
+    file.write_all(&png)?;
+    file.flush()?;
+
+    let next_seq = snd
+        .sequences
+        .last_key_value()
+        .map(|e| *e.0 + 1)
+        .unwrap_or_default();
+    let path = dir.join("SOUNDS").join(format!("SFX_{next_seq:03}.WAV"));
+    let mut file = create_new(&path)?;
+    sample_sound().write_wav(&mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_relative_to_project() {
+        let path = |p: &str| PathBuf::from(p);
+        let relative = |base, dir| relative_path(&path(base), &path(dir));
+        assert_eq!(
+            relative("/roms/doom64.z64", "/mods/mine"),
+            path("../../roms/doom64.z64")
+        );
+        assert_eq!(
+            relative("/mods/doom64.z64", "/mods/mine"),
+            path("../doom64.z64")
+        );
+        assert_eq!(
+            relative("/mods/mine/doom64.z64", "/mods/mine"),
+            path("doom64.z64")
+        );
+    }
+
+    #[test]
+    fn folder_table_rows() {
+        let table = folder_table();
+        assert_eq!(table.lines().count(), PROJECT_DIRS.len() + 2);
+        let row = |dir| {
+            table
+                .lines()
+                .find(|l| l.starts_with(&format!("| {dir} ")))
+                .unwrap()
+        };
+        assert!(row("MAPS").contains("| WAD, TXT "));
+        assert!(row("LIGHTS").contains("| TXT, PNG "));
+        assert!(row("MACROS").contains("| TXT "));
+    }
+}
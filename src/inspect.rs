@@ -1,8 +1,10 @@
-use std::{borrow::Cow, path::PathBuf};
+use std::{borrow::Cow, collections::BTreeMap, path::PathBuf};
 
 use crate::{
     extract::{self, PaletteCache, ReadFlags},
-    gfx, EntryName, FlatEntry, LumpType, WadEntry,
+    gfx,
+    map::{self, Map, ThingKind},
+    EntryName, FileFilters, FlatEntry, FlatWad, LumpType, WadEntry,
 };
 
 #[derive(clap::Args)]
@@ -15,6 +17,9 @@ pub struct Args {
     /// Test conversions
     #[arg(short, long, default_value_t = false)]
     test: bool,
+    /// Print statistics for each map instead of listing entries
+    #[arg(long, default_value_t = false, conflicts_with = "test")]
+    maps: bool,
     /// Optional WDD file to read when inspecting IWAD [default: DOOM64.WDD]
     #[arg(long)]
     wdd: Option<PathBuf>,
@@ -89,11 +94,93 @@ fn display_loop(lp: Option<&crate::sound::Loop>) -> String {
     .unwrap_or_default()
 }
 
+/// Logs the size, record counts and thing counts per skill of each map
+fn print_maps(wad: &FlatWad, filters: &FileFilters) {
+    const SKILLS: [u16; 3] = [map::THING_EASY, map::THING_NORMAL, map::THING_HARD];
+    for FlatEntry { name, entry } in &wad.entries {
+        let name = name.display();
+        if entry.typ != LumpType::Map || (!filters.is_empty() && !filters.matches(&name)) {
+            continue;
+        }
+        let map = match map::read_map_wad(entry).and_then(|wad| Map::read(&wad)) {
+            Ok(map) => map,
+            Err(e) => {
+                log::warn!("Failed to read map {name}: {e}");
+                continue;
+            }
+        };
+        log::info!(
+            "{name}: 0x{:x} bytes, 0x{:x} uncompressed",
+            entry.data.len(),
+            entry.uncompressed_len()
+        );
+        log::info!(
+            "  {} things, {} linedefs, {} sidedefs, {} sectors, {} vertexes, {} lights, {} macros",
+            map.things.len(),
+            map.linedefs.len(),
+            map.sidedefs.len(),
+            map.sectors.len(),
+            map.vertexes.len(),
+            map.lights.len(),
+            map.macros.as_ref().map_or(0, |m| m.macros.len()),
+        );
+
+        // counts for each skill, then for things only spawned in multiplayer
+        let mut types = BTreeMap::<i16, (ThingKind, [usize; 4])>::new();
+        let mut monsters = [0; 3];
+        let mut items = [0; 3];
+        for thing in &map.things {
+            let kind = thing.kind();
+            let counts = &mut types.entry(thing.typ).or_insert((kind, [0; 4])).1;
+            if thing.flags & map::THING_MULTI != 0 {
+                counts[3] += 1;
+                continue;
+            }
+            for (skill, flag) in SKILLS.into_iter().enumerate() {
+                if thing.flags & flag == 0 {
+                    continue;
+                }
+                counts[skill] += 1;
+                match kind {
+                    ThingKind::Monster => monsters[skill] += 1,
+                    ThingKind::Item => items[skill] += 1,
+                    _ => {}
+                }
+            }
+        }
+        let [easy, normal, hard] = monsters;
+        log::info!("  Monsters: {easy} easy, {normal} normal, {hard} hard");
+        let [easy, normal, hard] = items;
+        log::info!("  Items: {easy} easy, {normal} normal, {hard} hard");
+        log::info!(
+            "  Secrets: {} sectors, {} things",
+            map.sectors
+                .iter()
+                .filter(|s| s.flags & map::SECTOR_SECRET != 0)
+                .count(),
+            map.things
+                .iter()
+                .filter(|t| t.flags & map::THING_SECRET != 0)
+                .count(),
+        );
+        if !types.is_empty() {
+            log::info!("    TYPE  KIND    EASY  NORMAL HARD  MULTI");
+        }
+        for (typ, (kind, [easy, normal, hard, multi])) in types {
+            log::info!(
+                "    {typ: <5} {: <7} {easy: <5} {normal: <6} {hard: <5} {multi}",
+                format!("{kind:?}"),
+            );
+        }
+    }
+}
+
 pub fn inspect(args: Args) -> std::io::Result<()> {
     let Args {
         input,
         include,
         test,
+        maps,
         wdd,
         wmd,
         wsd,
@@ -110,6 +197,11 @@ pub fn inspect(args: Args) -> std::io::Result<()> {
         wsd,
         dls,
     };
+    if maps {
+        let (wad, _) = extract::read_rom_or_iwad(&input, ReadFlags::IWAD, &paths)?;
+        print_maps(&wad.unwrap(), &paths.filters);
+        return Ok(());
+    }
     let (wad, snd) = extract::read_rom_or_iwad(&input, ReadFlags::IWAD | ReadFlags::SOUND, &paths)?;
     let wad = wad.unwrap();
     log::info!("WAD Entries: {}", wad.entries.len());
@@ -1,13 +1,23 @@
 pub mod build;
 mod compression;
+mod convert;
 pub mod extract;
 mod gfx;
+pub mod init;
 pub mod inspect;
+pub mod lint;
+mod lock;
 mod lumps;
+pub mod map;
 mod music;
+mod nodes;
+pub mod pack;
+mod project;
 mod remaster;
+pub mod render;
 mod sound;
 mod soundfont;
+pub mod verify;
 mod wad;
 
 pub use wad::*;
@@ -86,3 +96,20 @@ fn invalid_data(args: impl std::fmt::Display) -> std::io::Error {
 fn is_log_level(lvl: log::LevelFilter) -> bool {
     lvl <= log::STATIC_MAX_LEVEL && lvl <= log::max_level()
 }
+
+/// Returned by a command that finished but had to skip some inputs because of errors
+#[derive(Debug)]
+pub struct SkippedErrors(pub usize);
+
+impl SkippedErrors {
+    /// Process exit code for a run that skipped errors, to tell it apart from a failed run
+    pub const EXIT_CODE: u8 = 2;
+}
+
+impl std::fmt::Display for SkippedErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Finished, but skipped {} files with errors", self.0)
+    }
+}
+
+impl std::error::Error for SkippedErrors {}
@@ -0,0 +1,23 @@
+use std::{io, path::PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Directories and ROMs to check, in the same order they would be built
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Glob patterns to exclude entry names
+    #[arg(short, long)]
+    exclude: Vec<String>,
+    /// Treat files with an extension their folder doesn't accept as errors
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+}
+
+pub fn lint(args: Args) -> io::Result<()> {
+    let Args {
+        inputs,
+        exclude,
+        strict,
+    } = args;
+    crate::build::lint(inputs, exclude, strict)
+}
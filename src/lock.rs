@@ -0,0 +1,243 @@
+use crate::{
+    invalid_data,
+    project::parse_ini,
+    sound::{Loop, Sample},
+    Compression, EntryName, LumpType,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::Path,
+};
+
+/// File name of the sidecar written next to extracted files
+pub const LOCK_NAME: &str = "d64make.lock";
+
+const HEADER: &str = "\
+# Written by `d64make extract` and read by `d64make build` to restore details
+# of the original IWAD. Delete this file to build with the defaults instead.
+";
+
+/// A WAD entry as it appeared in the extracted IWAD
+#[derive(Clone, Debug)]
+pub struct LockEntry {
+    pub name: EntryName,
+    pub typ: LumpType,
+    pub compression: Compression,
+}
+
+/// An extracted file, keyed by its `/`-separated path relative to the lock
+#[derive(Clone, Debug)]
+pub struct LockFile {
+    pub typ: LumpType,
+    pub hash: blake3::Hash,
+}
+
+#[derive(Clone, Debug)]
+pub struct LockSound {
+    pub priority: u8,
+    pub volume: u8,
+    pub r#loop: Option<Loop>,
+}
+
+/// Details of an extracted IWAD that the extracted files can't hold, so that `build` can
+/// recreate it faithfully
+#[derive(Clone, Debug, Default)]
+pub struct Lock {
+    /// Entries in their original WAD order
+    pub entries: Vec<LockEntry>,
+    pub files: BTreeMap<String, LockFile>,
+    /// Sprites that reference the palette of an earlier sprite or palette lump
+    pub palettes: BTreeMap<EntryName, EntryName>,
+    pub sounds: BTreeMap<u16, LockSound>,
+    positions: HashMap<(LumpType, EntryName), usize>,
+}
+
+fn parse_field<T: std::str::FromStr>(
+    value: Option<&str>,
+    line: usize,
+    what: &str,
+) -> io::Result<T> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid_data(format_args!("line {line}: invalid or missing {what}")))
+}
+
+fn parse_name(value: Option<&str>, line: usize) -> io::Result<EntryName> {
+    value
+        .and_then(EntryName::new)
+        .ok_or_else(|| invalid_data(format_args!("line {line}: invalid entry name")))
+}
+
+impl Lock {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut lock = Self::default();
+        for entry in parse_ini(text)? {
+            let line = entry.line;
+            let mut fields = entry.value.split_whitespace();
+            match entry.section {
+                "entries" => {
+                    let index: usize = parse_field(Some(entry.key), line, "index")?;
+                    if index != lock.entries.len() {
+                        return Err(invalid_data(format_args!(
+                            "line {line}: expected entry {}",
+                            lock.entries.len()
+                        )));
+                    }
+                    let name = parse_name(fields.next(), line)?;
+                    let typ = fields
+                        .next()
+                        .and_then(LumpType::from_name)
+                        .ok_or_else(|| invalid_data(format_args!("line {line}: invalid type")))?;
+                    let compression = fields.next().and_then(Compression::from_name);
+                    let compression = compression.ok_or_else(|| {
+                        invalid_data(format_args!("line {line}: bad compression"))
+                    })?;
+                    lock.entries.push(LockEntry {
+                        name,
+                        typ,
+                        compression,
+                    });
+                }
+                "files" => {
+                    let typ = fields
+                        .next()
+                        .and_then(LumpType::from_name)
+                        .ok_or_else(|| invalid_data(format_args!("line {line}: invalid type")))?;
+                    let hash = fields
+                        .next()
+                        .and_then(|h| blake3::Hash::from_hex(h).ok())
+                        .ok_or_else(|| invalid_data(format_args!("line {line}: invalid hash")))?;
+                    lock.files
+                        .insert(entry.key.to_owned(), LockFile { typ, hash });
+                }
+                "palettes" => {
+                    let sprite = parse_name(Some(entry.key), line)?;
+                    let source = parse_name(fields.next(), line)?;
+                    lock.palettes.insert(sprite, source);
+                }
+                "sounds" => {
+                    let id = parse_field(Some(entry.key), line, "sound id")?;
+                    let priority = parse_field(fields.next(), line, "priority")?;
+                    let volume = parse_field(fields.next(), line, "volume")?;
+                    let r#loop = match fields.next() {
+                        Some(start) => Some(Loop {
+                            start: parse_field(Some(start), line, "loop start")?,
+                            end: parse_field(fields.next(), line, "loop end")?,
+                            count: parse_field(fields.next(), line, "loop count")?,
+                        }),
+                        None => None,
+                    };
+                    lock.sounds.insert(
+                        id,
+                        LockSound {
+                            priority,
+                            volume,
+                            r#loop,
+                        },
+                    );
+                }
+                _ => log::warn!(
+                    "{LOCK_NAME}:{line}: unknown key `{}` in section [{}]",
+                    entry.key,
+                    entry.section
+                ),
+            }
+        }
+        lock.index();
+        Ok(lock)
+    }
+    /// Reads the lock in an extracted directory, if there is one
+    pub fn read(dir: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let path = dir.as_ref().join(LOCK_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text)
+                .map(Some)
+                .map_err(|e| invalid_data(format_args!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(HEADER.as_bytes())?;
+        writeln!(w, "[entries]")?;
+        writeln!(w, "# index = name type compression")?;
+        for (index, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                w,
+                "{index} = {} {:?} {}",
+                entry.name.display(),
+                entry.typ,
+                entry.compression.name()
+            )?;
+        }
+        writeln!(w, "[files]")?;
+        writeln!(w, "# path = type blake3")?;
+        for (path, file) in &self.files {
+            writeln!(w, "{path} = {:?} {}", file.typ, file.hash)?;
+        }
+        writeln!(w, "[palettes]")?;
+        writeln!(w, "# sprite = entry holding its palette")?;
+        for (sprite, source) in &self.palettes {
+            writeln!(w, "{} = {}", sprite.display(), source.display())?;
+        }
+        writeln!(w, "[sounds]")?;
+        writeln!(w, "# id = priority volume [loop_start loop_end loop_count]")?;
+        for (id, sound) in &self.sounds {
+            write!(w, "{id} = {} {}", sound.priority, sound.volume)?;
+            if let Some(r#loop) = &sound.r#loop {
+                write!(w, " {} {} {}", r#loop.start, r#loop.end, r#loop.count)?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+    fn index(&mut self) {
+        self.positions = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| ((entry.typ, entry.name.clone()), index))
+            .collect();
+    }
+    /// Adds another lock on top of this one, replacing any details both of them have
+    pub fn merge(&mut self, other: &Self) {
+        self.entries.extend(other.entries.iter().cloned());
+        self.files.extend(other.files.clone());
+        self.palettes.extend(other.palettes.clone());
+        self.sounds.extend(other.sounds.clone());
+        self.index();
+    }
+    #[inline]
+    pub fn position(&self, typ: LumpType, name: &EntryName) -> Option<usize> {
+        self.positions.get(&(typ, name.clone())).copied()
+    }
+    pub fn compression(&self, typ: LumpType, name: &EntryName) -> Compression {
+        self.position(typ, name)
+            .map(|index| self.entries[index].compression)
+            .unwrap_or(Compression::None)
+    }
+    /// Returns `true` if a file still has the contents it was extracted with
+    pub fn is_unchanged(&self, path: &str, data: &[u8]) -> bool {
+        self.files
+            .get(path)
+            .is_some_and(|file| file.hash == blake3::hash(data))
+    }
+    /// Restores the extracted settings of a sound effect or music sample. Settings in an
+    /// edited file take precedence unless they are the defaults, which usually means an audio
+    /// editor dropped the chunks holding them.
+    pub fn apply_sound(&self, id: u16, sample: &mut Sample, unchanged: bool) {
+        let Some(sound) = self.sounds.get(&id) else {
+            return;
+        };
+        if unchanged || sample.priority == Sample::DEFAULT_PRIORITY {
+            sample.priority = sound.priority;
+        }
+        if unchanged || sample.volume == Sample::DEFAULT_VOLUME {
+            sample.volume = sound.volume;
+        }
+        if unchanged || sample.info.r#loop.is_none() {
+            sample.info.r#loop = sound.r#loop.clone();
+        }
+    }
+}
@@ -1017,6 +1017,26 @@ pub const FLAT_ORDER: &[&[u8]] = &[
     b"F_SKYI", b"F_SKYJ", b"F_SKYK",
 ];
 
+/// Textures and flats the game animates, which it looks up by name when it starts, with the
+/// number of frames that follow in `T_START` order. Palette cycles have a single frame.
+pub const ANIMATIONS: &[(&[u8], usize)] = &[
+    (b"SMONAA", 4),
+    (b"SMONBA", 4),
+    (b"SMONCA", 4),
+    (b"CFACEA", 3),
+    (b"SMONDA", 4),
+    (b"SMONEA", 4),
+    (b"SPORTA", 1),
+    (b"SMONF", 1),
+    (b"STRAKR", 1),
+    (b"STRAKB", 1),
+    (b"STRAKY", 1),
+    (b"C307B", 1),
+    (b"CTEL", 1),
+    (b"CASFL98", 1),
+    (b"HTELA", 4),
+];
+
 // lump name => (hash, [(offset, data)])
 #[allow(clippy::type_complexity)]
 pub const VANILLA_FIXES: phf::Map<&[u8], ([u8; 32], &[(usize, &[u8])])> = phf::phf_map! {
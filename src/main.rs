@@ -18,6 +18,16 @@ enum Commands {
     Build(build::Args),
     /// Inspects ROM or IWAD
     Inspect(inspect::Args),
+    /// Creates a new mod project directory
+    Init(init::Args),
+    /// Checks a project directory and packs it into a PK3
+    Pack(pack::Args),
+    /// Loads inputs like `build` and reports every error and map problem without writing
+    Lint(lint::Args),
+    /// Extracts and rebuilds ROM or IWAD, then compares the result with the original
+    Verify(verify::Args),
+    /// Draws a map from ROM or IWAD to a PNG image
+    RenderMap(render::Args),
 }
 
 fn main() -> ExitCode {
@@ -38,12 +48,20 @@ fn main() -> ExitCode {
         Commands::Extract(args) => extract::extract(args),
         Commands::Build(args) => build::build(args),
         Commands::Inspect(args) => inspect::inspect(args),
+        Commands::Init(args) => init::init(args),
+        Commands::Pack(args) => pack::pack(args),
+        Commands::Lint(args) => lint::lint(args),
+        Commands::Verify(args) => verify::verify(args),
+        Commands::RenderMap(args) => render::render_map(args),
     };
     match res {
         Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             log::error!("{e}");
-            ExitCode::FAILURE
+            match e.get_ref().is_some_and(|e| e.is::<SkippedErrors>()) {
+                true => ExitCode::from(SkippedErrors::EXIT_CODE),
+                false => ExitCode::FAILURE,
+            }
         }
     }
 }
@@ -0,0 +1,1338 @@
+use crate::{
+    convert_error,
+    extract::WadType,
+    gfx::{self, RGBA},
+    invalid_data,
+    project::parse_ini,
+    sound::NoSeekWrite,
+    EntryName, FlatEntry, FlatWad, LumpType, WadEntry,
+};
+use binrw::BinRead;
+use itertools::Itertools;
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor},
+};
+
+/// Lumps of a map WAD in the order the engine expects them after the map marker
+pub const LUMPS: [&str; 13] = [
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT",
+    "BLOCKMAP", "LEAFS", "LIGHTS", "MACROS",
+];
+
+/// Sector color index of the first entry in LIGHTS
+pub const FIRST_LIGHT: u16 = 256;
+
+/// 16.16 fixed point number
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Fixed(pub i32);
+
+impl Fixed {
+    pub const FRACBITS: u32 = 16;
+    pub const ONE: Self = Self(1 << Self::FRACBITS);
+
+    #[inline]
+    pub fn from_int(i: i16) -> Self {
+        Self((i as i32) << Self::FRACBITS)
+    }
+    /// Rounds towards negative infinity, as the engine does
+    #[inline]
+    pub fn to_int(self) -> i16 {
+        (self.0 >> Self::FRACBITS) as i16
+    }
+    #[inline]
+    pub fn from_f64(f: f64) -> Self {
+        Self((f * Self::ONE.0 as f64).round() as i32)
+    }
+    #[inline]
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::ONE.0 as f64
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Thing {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    pub angle: i16,
+    pub typ: i16,
+    pub flags: u16,
+    pub tid: i16,
+}
+
+pub const THING_EASY: u16 = 0x1;
+pub const THING_NORMAL: u16 = 0x2;
+pub const THING_HARD: u16 = 0x4;
+/// Waits until it sees a player instead of waking up on noise
+pub const THING_AMBUSH: u16 = 0x8;
+/// Only spawned in multiplayer games
+pub const THING_MULTI: u16 = 0x10;
+/// Counts as a secret when picked up
+pub const THING_SECRET: u16 = 0x100;
+
+/// Rough category of a thing type, for drawing and counting
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ThingKind {
+    Player,
+    Monster,
+    Key,
+    Item,
+    Other,
+}
+
+impl Thing {
+    pub fn kind(&self) -> ThingKind {
+        match self.typ {
+            // 3008 to 3010 are marine bots
+            1..=4 | 11 | 3008..=3010 => ThingKind::Player,
+            // 3013 is the Mother Demon, the title map's Cyberdemon (3014) is scenery
+            9 | 16 | 58 | 66..=69 | 71 | 3001..=3007 | 3013 => ThingKind::Monster,
+            5 | 6 | 13 | 38..=40 => ThingKind::Key,
+            8 | 17 | 82 | 83 | 2001..=2099 => ThingKind::Item,
+            _ => ThingKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Linedef {
+    pub v1: u16,
+    pub v2: u16,
+    pub flags: u32,
+    pub special: u16,
+    pub tag: i16,
+    /// Front and back sidedef, `0xffff` if there is none
+    pub sides: [u16; 2],
+}
+
+pub const LINE_BLOCKING: u32 = 0x1;
+pub const LINE_TWO_SIDED: u32 = 0x4;
+/// Drawn as a one-sided line on the automap
+pub const LINE_SECRET: u32 = 0x20;
+/// Never drawn on the automap
+pub const LINE_DONT_DRAW: u32 = 0x80;
+
+/// Bit of a linedef special that makes the action a macro number
+pub const SPECIAL_MACRO: u16 = 0x100;
+pub const SPECIAL_RED_KEY: u16 = 0x200;
+pub const SPECIAL_BLUE_KEY: u16 = 0x400;
+pub const SPECIAL_YELLOW_KEY: u16 = 0x800;
+
+/// Actions that end the level, going to the map numbered by the tag. The normal exit (52)
+/// ignores its tag and always goes to the next map number.
+pub const EXIT_ACTIONS: [u16; 1] = [124];
+
+impl Linedef {
+    /// Action number or macro number of the special, without the trigger and key bits
+    #[inline]
+    pub fn action(&self) -> u16 {
+        self.special & 0xff
+    }
+}
+
+/// Number of a map named `MAPnn`
+pub fn map_number(name: &str) -> Option<u16> {
+    let digits = name.strip_prefix("MAP")?;
+    match digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => digits.parse().ok(),
+        false => None,
+    }
+}
+
+#[inline]
+pub fn map_name(number: u16) -> String {
+    format!("MAP{number:02}")
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Sidedef {
+    pub x_offset: i16,
+    pub y_offset: i16,
+    /// Indices into the textures between `T_START` and `T_END`
+    pub top: u16,
+    pub bottom: u16,
+    pub middle: u16,
+    pub sector: u16,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Vertex {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Seg {
+    pub v1: u16,
+    pub v2: u16,
+    pub angle: u16,
+    pub linedef: u16,
+    pub side: u16,
+    pub offset: i16,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Subsector {
+    pub seg_count: u16,
+    pub first_seg: u16,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Node {
+    pub x: i16,
+    pub y: i16,
+    pub dx: i16,
+    pub dy: i16,
+    /// Right and left bounding boxes as top, bottom, left, right
+    pub bbox: [[i16; 4]; 2],
+    /// Right and left children, with the high bit set for subsectors
+    pub children: [u16; 2],
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Sector {
+    pub floor_height: i16,
+    pub ceiling_height: i16,
+    /// Indices into the textures between `T_START` and `T_END`
+    pub floor: u16,
+    pub ceiling: u16,
+    /// Colors of the floor, ceiling, things, upper walls and lower walls. Indices below
+    /// [`FIRST_LIGHT`] are shades of grey and the rest refer to LIGHTS.
+    pub colors: [u16; 5],
+    pub special: i16,
+    pub tag: i16,
+    pub flags: u16,
+}
+
+/// Counts as a secret when a player enters the sector
+pub const SECTOR_SECRET: u16 = 0x20;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct Light {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+    pub tag: i16,
+}
+
+/// Closed polygon of a subsector, listing each vertex with the seg that starts from it
+#[binrw::binrw]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[brw(little)]
+pub struct Leaf {
+    #[br(temp)]
+    #[bw(calc = vertices.len() as u16)]
+    count: u16,
+    #[br(count = count)]
+    pub vertices: Vec<LeafVertex>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct LeafVertex {
+    pub vertex: u16,
+    pub seg: u16,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Blockmap {
+    pub x: i16,
+    pub y: i16,
+    pub columns: u16,
+    pub rows: u16,
+    /// Offset in 16-bit words from the start of the lump to the list of each block
+    pub offsets: Vec<u16>,
+    /// Line lists the offsets point into, kept as stored so shared lists survive
+    pub lists: Vec<u16>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Macros {
+    /// Action count from the header, which the engine only uses to size its allocation
+    pub specials: u16,
+    pub macros: Vec<Macro>,
+}
+
+/// Actions run in batches when a linedef with the macro's special is triggered
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Macro {
+    /// Every action including the one ending the macro
+    pub actions: Vec<MacroAction>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct MacroAction {
+    pub batch: u16,
+    pub tag: i16,
+    pub special: u16,
+}
+
+/// Parsed lumps of a map WAD. Lumps missing from the WAD are left empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Map {
+    pub things: Vec<Thing>,
+    pub linedefs: Vec<Linedef>,
+    pub sidedefs: Vec<Sidedef>,
+    pub vertexes: Vec<Vertex>,
+    pub segs: Vec<Seg>,
+    pub subsectors: Vec<Subsector>,
+    pub nodes: Vec<Node>,
+    pub sectors: Vec<Sector>,
+    /// One bit for each pair of sectors that can't see each other
+    pub reject: Vec<u8>,
+    pub blockmap: Option<Blockmap>,
+    pub leafs: Vec<Leaf>,
+    pub lights: Vec<Light>,
+    pub macros: Option<Macros>,
+}
+
+pub(crate) fn read_records<T>(data: &[u8]) -> binrw::BinResult<Vec<T>>
+where
+    T: BinRead + binrw::meta::ReadEndian,
+    for<'a> T::Args<'a>: Default,
+{
+    let mut cursor = Cursor::new(data);
+    let mut records = Vec::new();
+    while (cursor.position() as usize) < data.len() {
+        records.push(T::read(&mut cursor)?);
+    }
+    Ok(records)
+}
+
+fn write_records<T: NoSeekWrite>(records: &[T], w: &mut impl io::Write) -> io::Result<()> {
+    for record in records {
+        record.write_no_seek(w)?;
+    }
+    Ok(())
+}
+
+fn read_words(data: &[u8]) -> io::Result<Vec<u16>> {
+    let words = data.chunks_exact(2);
+    if !words.remainder().is_empty() {
+        return Err(invalid_data("odd number of bytes"));
+    }
+    Ok(words.map(|w| u16::from_le_bytes([w[0], w[1]])).collect())
+}
+
+impl Blockmap {
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let words = read_words(data)?;
+        if words.len() < 4 {
+            return Err(invalid_data("truncated header"));
+        }
+        let (header, rest) = words.split_at(4);
+        let [x, y, columns, rows] = [header[0], header[1], header[2], header[3]];
+        let blocks = columns as usize * rows as usize;
+        if rest.len() < blocks {
+            return Err(invalid_data(format_args!(
+                "{blocks} blocks but only room for {} offsets",
+                rest.len()
+            )));
+        }
+        let (offsets, lists) = rest.split_at(blocks);
+        Ok(Self {
+            x: x as i16,
+            y: y as i16,
+            columns,
+            rows,
+            offsets: offsets.to_vec(),
+            lists: lists.to_vec(),
+        })
+    }
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        let header = [self.x as u16, self.y as u16, self.columns, self.rows];
+        for word in header.iter().chain(&self.offsets).chain(&self.lists) {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl Macros {
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(data);
+        let mut word = || -> io::Result<u16> {
+            let w = u16::read_le(&mut cursor).map_err(|_| invalid_data("truncated"))?;
+            Ok(w)
+        };
+        let count = word()?;
+        let specials = word()?;
+        let mut macros = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let actions = word()? as usize + 1;
+            let actions = (0..actions)
+                .map(|_| {
+                    Ok(MacroAction {
+                        batch: word()?,
+                        tag: word()? as i16,
+                        special: word()?,
+                    })
+                })
+                .collect::<io::Result<_>>()?;
+            macros.push(Macro { actions });
+        }
+        if (cursor.position() as usize) < data.len() {
+            return Err(invalid_data("trailing data"));
+        }
+        Ok(Self { specials, macros })
+    }
+    /// Number of actions in all macros, not counting the ones ending them, for
+    /// [`specials`](Self::specials)
+    pub fn special_count(&self) -> usize {
+        self.macros.iter().map(|m| m.actions.len() - 1).sum()
+    }
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(&(self.macros.len() as u16).to_le_bytes())?;
+        w.write_all(&self.specials.to_le_bytes())?;
+        for m in &self.macros {
+            w.write_all(&(m.actions.len() as u16 - 1).to_le_bytes())?;
+            write_records(&m.actions, w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Map {
+    /// Reads the map lumps of a map WAD, ignoring any other entries
+    pub fn read(wad: &FlatWad) -> io::Result<Self> {
+        let mut map = Self::default();
+        for entry in &wad.entries {
+            let name = entry.name.display();
+            if !LUMPS.contains(&name.as_ref()) {
+                continue;
+            }
+            let lump = entry.entry.decompress()?;
+            map.parse_lump(&name, &lump.data)
+                .map_err(|e| invalid_data(format_args!("{name}: {e}")))?;
+        }
+        Ok(map)
+    }
+    fn parse_lump(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        fn records<T>(data: &[u8]) -> io::Result<Vec<T>>
+        where
+            T: BinRead + binrw::meta::ReadEndian,
+            for<'a> T::Args<'a>: Default,
+        {
+            read_records(data).map_err(|_| invalid_data("truncated record"))
+        }
+        match name {
+            "THINGS" => self.things = records(data)?,
+            "LINEDEFS" => self.linedefs = records(data)?,
+            "SIDEDEFS" => self.sidedefs = records(data)?,
+            "VERTEXES" => self.vertexes = records(data)?,
+            "SEGS" => self.segs = records(data)?,
+            "SSECTORS" => self.subsectors = records(data)?,
+            "NODES" => self.nodes = records(data)?,
+            "SECTORS" => self.sectors = records(data)?,
+            "REJECT" => self.reject = data.to_vec(),
+            "BLOCKMAP" if data.is_empty() => self.blockmap = None,
+            "BLOCKMAP" => self.blockmap = Some(Blockmap::parse(data)?),
+            "LEAFS" => self.leafs = records(data)?,
+            "LIGHTS" => self.lights = records(data)?,
+            "MACROS" if data.is_empty() => self.macros = None,
+            "MACROS" => self.macros = Some(Macros::parse(data)?),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+    /// Serializes one of the lumps in [`LUMPS`]
+    pub fn write_lump(&self, name: &str, w: &mut impl io::Write) -> io::Result<()> {
+        match name {
+            "THINGS" => write_records(&self.things, w),
+            "LINEDEFS" => write_records(&self.linedefs, w),
+            "SIDEDEFS" => write_records(&self.sidedefs, w),
+            "VERTEXES" => write_records(&self.vertexes, w),
+            "SEGS" => write_records(&self.segs, w),
+            "SSECTORS" => write_records(&self.subsectors, w),
+            "NODES" => write_records(&self.nodes, w),
+            "SECTORS" => write_records(&self.sectors, w),
+            "REJECT" => w.write_all(&self.reject),
+            "BLOCKMAP" => self.blockmap.as_ref().map_or(Ok(()), |b| b.write(w)),
+            "LEAFS" => write_records(&self.leafs, w),
+            "LIGHTS" => write_records(&self.lights, w),
+            "MACROS" => self.macros.as_ref().map_or(Ok(()), |m| m.write(w)),
+            _ => Err(invalid_data(format_args!("{name} is not a map lump"))),
+        }
+    }
+    /// Writes the lumps back into a map WAD, keeping the order and compression of the
+    /// entries already there and adding missing lumps before `ENDOFWAD`
+    pub fn write(&self, wad: &mut FlatWad) -> io::Result<()> {
+        for name in LUMPS {
+            let mut data = Vec::new();
+            self.write_lump(name, &mut data)?;
+            replace_lump(wad, name, data);
+        }
+        Ok(())
+    }
+    /// Creates a map WAD starting with a marker named after the map
+    pub fn to_wad(&self, name: &str) -> io::Result<FlatWad> {
+        let mut wad = FlatWad {
+            entries: vec![FlatEntry::marker(name)],
+        };
+        self.write(&mut wad)?;
+        Ok(wad)
+    }
+    /// Points the secret exits of linedefs and macros going to a map number in `slots` at the
+    /// number it maps to, returning whether any changed
+    pub fn move_exits(&mut self, slots: &BTreeMap<u16, u16>) -> bool {
+        let mut changed = false;
+        let mut exit = |action: u16, tag: &mut i16| {
+            if !EXIT_ACTIONS.contains(&action) {
+                return;
+            }
+            let to = u16::try_from(*tag).ok().and_then(|n| slots.get(&n));
+            if let Some(&to) = to {
+                *tag = to as i16;
+                changed = true;
+            }
+        };
+        for line in &mut self.linedefs {
+            if line.special & SPECIAL_MACRO == 0 {
+                exit(line.action(), &mut line.tag);
+            }
+        }
+        for m in self.macros.iter_mut().flat_map(|m| &mut m.macros) {
+            for action in &mut m.actions {
+                exit(action.special & 0xff, &mut action.tag);
+            }
+        }
+        changed
+    }
+}
+
+/// Replaces a lump of a map WAD, keeping its compression, or adds it before `ENDOFWAD`
+pub fn replace_lump(wad: &mut FlatWad, name: &str, data: Vec<u8>) {
+    let existing = wad
+        .entries
+        .iter_mut()
+        .find(|e| e.name.0.as_slice() == name.as_bytes());
+    match existing {
+        Some(entry) => {
+            let compression = entry.entry.compression;
+            entry.entry = WadEntry::new(LumpType::MapLump, data);
+            entry.entry.compress(compression);
+        }
+        None => {
+            let end = wad
+                .entries
+                .iter()
+                .position(|e| e.name.0.as_slice() == b"ENDOFWAD")
+                .unwrap_or(wad.entries.len());
+            let entry = FlatEntry::new(name, LumpType::MapLump, data);
+            wad.entries.insert(end, entry);
+        }
+    }
+}
+
+/// Decompresses and parses the WAD stored in a map entry of an IWAD
+pub fn read_map_wad(entry: &WadEntry<Vec<u8>>) -> io::Result<FlatWad> {
+    let entry = entry.decompress()?;
+    Ok(
+        FlatWad::parse(&entry.data, WadType::N64Map, true, &Default::default())
+            .map_err(|e| invalid_data(convert_error(entry.data.as_slice(), e)))?
+            .1,
+    )
+}
+
+/// Value of a sidedef, node child or leaf seg reference that points at nothing
+pub const NONE: u16 = 0xffff;
+
+/// Bit set on a node child that refers to a subsector instead of another node
+pub const NODE_SUBSECTOR: u16 = 0x8000;
+
+impl Map {
+    /// Checks that the records of the map only refer to records that exist, and that the
+    /// textures and flats they use are among the first `textures` entries after `T_START`.
+    /// Returns a description of each problem.
+    pub fn validate(&self, textures: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &dyn Fn() -> String| {
+            if !ok {
+                problems.push(problem());
+            }
+        };
+        let lights = FIRST_LIGHT as usize + self.lights.len();
+        for (i, sector) in self.sectors.iter().enumerate() {
+            for (what, tex) in [("floor", sector.floor), ("ceiling", sector.ceiling)] {
+                check((tex as usize) < textures, &|| {
+                    format!("sector {i}: {what} flat {tex} is not one of the {textures} textures")
+                });
+            }
+            for color in sector.colors {
+                check((color as usize) < lights, &|| {
+                    format!("sector {i}: light {color} is not in LIGHTS, which ends at {lights}")
+                });
+            }
+        }
+        for (i, side) in self.sidedefs.iter().enumerate() {
+            let sides = [
+                ("top", side.top),
+                ("bottom", side.bottom),
+                ("middle", side.middle),
+            ];
+            for (what, tex) in sides {
+                check((tex as usize) < textures, &|| {
+                    format!(
+                        "sidedef {i}: {what} texture {tex} is not one of the {textures} textures"
+                    )
+                });
+            }
+            check((side.sector as usize) < self.sectors.len(), &|| {
+                format!("sidedef {i}: sector {} does not exist", side.sector)
+            });
+        }
+        for (i, line) in self.linedefs.iter().enumerate() {
+            for v in [line.v1, line.v2] {
+                check((v as usize) < self.vertexes.len(), &|| {
+                    format!("linedef {i}: vertex {v} does not exist")
+                });
+            }
+            let [front, back] = line.sides;
+            check((front as usize) < self.sidedefs.len(), &|| {
+                format!("linedef {i}: front sidedef {front} does not exist")
+            });
+            check(
+                back == NONE || (back as usize) < self.sidedefs.len(),
+                &|| format!("linedef {i}: back sidedef {back} does not exist"),
+            );
+        }
+        for (i, seg) in self.segs.iter().enumerate() {
+            for v in [seg.v1, seg.v2] {
+                check((v as usize) < self.vertexes.len(), &|| {
+                    format!("seg {i}: vertex {v} does not exist")
+                });
+            }
+            check((seg.linedef as usize) < self.linedefs.len(), &|| {
+                format!("seg {i}: linedef {} does not exist", seg.linedef)
+            });
+            check(seg.side <= 1, &|| {
+                format!("seg {i}: side {} is not 0 or 1", seg.side)
+            });
+        }
+        for (i, sub) in self.subsectors.iter().enumerate() {
+            let end = sub.first_seg as usize + sub.seg_count as usize;
+            check(end <= self.segs.len(), &|| {
+                format!(
+                    "subsector {i}: segs {}..{end} are past the end of SEGS ({})",
+                    sub.first_seg,
+                    self.segs.len()
+                )
+            });
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            for child in node.children {
+                let (what, index, count) = match child & NODE_SUBSECTOR {
+                    0 => ("node", child, self.nodes.len()),
+                    _ => ("subsector", child & !NODE_SUBSECTOR, self.subsectors.len()),
+                };
+                check((index as usize) < count, &|| {
+                    format!("node {i}: child {what} {index} does not exist")
+                });
+            }
+        }
+        check(
+            !self.nodes.is_empty() || self.subsectors.len() <= 1,
+            &|| format!("no NODES for {} subsectors", self.subsectors.len()),
+        );
+        check(self.leafs.len() == self.subsectors.len(), &|| {
+            format!(
+                "LEAFS has {} entries but SSECTORS has {}",
+                self.leafs.len(),
+                self.subsectors.len()
+            )
+        });
+        for (i, leaf) in self.leafs.iter().enumerate() {
+            for v in &leaf.vertices {
+                check((v.vertex as usize) < self.vertexes.len(), &|| {
+                    format!("leaf {i}: vertex {} does not exist", v.vertex)
+                });
+                check(v.seg == NONE || (v.seg as usize) < self.segs.len(), &|| {
+                    format!("leaf {i}: seg {} does not exist", v.seg)
+                });
+            }
+        }
+        problems
+    }
+}
+
+/// Validates every map in an IWAD against its texture section, returning each problem
+/// prefixed with the map name
+pub fn validate_maps(iwad: &FlatWad) -> Vec<String> {
+    let position = |name: &[u8]| {
+        iwad.entries
+            .iter()
+            .position(|e| e.name.0.as_slice() == name)
+    };
+    let textures = match (position(b"T_START"), position(b"T_END")) {
+        (Some(start), Some(end)) => end.saturating_sub(start + 1),
+        _ => 0,
+    };
+    let mut problems = Vec::new();
+    for entry in &iwad.entries {
+        if entry.entry.typ != LumpType::Map {
+            continue;
+        }
+        let name = entry.name.display();
+        let map = read_map_wad(&entry.entry).and_then(|wad| Map::read(&wad));
+        match map {
+            Ok(map) => problems.extend(
+                map.validate(textures)
+                    .into_iter()
+                    .map(|problem| format!("{name} {problem}")),
+            ),
+            Err(e) => problems.push(format!("{name}: {e}")),
+        }
+    }
+    problems
+}
+
+const TEXT_HEADER: &str = "\
+# Doom 64 map written by `d64make extract --map-text` and read back by `d64make build`.
+# Sections after [macros] hold node, blockmap and reject data as they were extracted.
+";
+
+/// Number of words or bytes on each line of the blockmap and reject sections
+const TEXT_ROW: usize = 16;
+
+/// Whitespace separated fields of a line in a text map
+struct Fields<'a> {
+    line: usize,
+    fields: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: usize, value: &'a str) -> Self {
+        Self {
+            line,
+            fields: value.split_whitespace(),
+        }
+    }
+    fn int<T: TryFrom<i64>>(&mut self, what: &str) -> io::Result<T> {
+        let line = self.line;
+        self.fields
+            .next()
+            .and_then(parse_int)
+            .and_then(|i| T::try_from(i).ok())
+            .ok_or_else(|| invalid_data(format_args!("line {line}: invalid or missing {what}")))
+    }
+    fn fixed(&mut self, what: &str) -> io::Result<Fixed> {
+        let line = self.line;
+        self.fields
+            .next()
+            .and_then(|f| f.parse::<f64>().ok())
+            .filter(|f| (-32768.0..32768.0).contains(f))
+            .map(Fixed::from_f64)
+            .ok_or_else(|| invalid_data(format_args!("line {line}: invalid or missing {what}")))
+    }
+    fn rest(&mut self) -> &mut std::str::SplitWhitespace<'a> {
+        &mut self.fields
+    }
+    fn end(mut self) -> io::Result<()> {
+        match self.fields.next() {
+            Some(extra) => Err(invalid_data(format_args!(
+                "line {}: unexpected `{extra}`",
+                self.line
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal integer
+fn parse_int(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Checks that the key of a record line is the index of the next record
+fn check_index(key: &str, line: usize, expected: usize) -> io::Result<()> {
+    if key.parse::<usize>().ok() != Some(expected) {
+        return Err(invalid_data(format_args!(
+            "line {line}: expected record {expected}"
+        )));
+    }
+    Ok(())
+}
+
+impl Map {
+    /// Writes the map as text, listing the entries of `wad` so their order can be restored
+    pub fn write_text(&self, wad: &FlatWad, w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(TEXT_HEADER.as_bytes())?;
+        writeln!(w, "[wad]")?;
+        let entries = wad.entries.iter().map(|e| e.name.display()).join(" ");
+        writeln!(w, "entries = {entries}")?;
+        writeln!(w, "[things]")?;
+        writeln!(w, "# index = x y z angle type flags tid")?;
+        for (i, t) in self.things.iter().enumerate() {
+            writeln!(
+                w,
+                "{i} = {} {} {} {} {} 0x{:x} {}",
+                t.x, t.y, t.z, t.angle, t.typ, t.flags, t.tid
+            )?;
+        }
+        writeln!(w, "[vertexes]")?;
+        writeln!(w, "# index = x y")?;
+        for (i, v) in self.vertexes.iter().enumerate() {
+            writeln!(w, "{i} = {} {}", v.x.to_f64(), v.y.to_f64())?;
+        }
+        writeln!(w, "[linedefs]")?;
+        writeln!(w, "# index = v1 v2 flags special tag front back")?;
+        for (i, l) in self.linedefs.iter().enumerate() {
+            let [front, back] = l.sides;
+            writeln!(
+                w,
+                "{i} = {} {} 0x{:x} {} {} {front} {back}",
+                l.v1, l.v2, l.flags, l.special, l.tag
+            )?;
+        }
+        writeln!(w, "[sidedefs]")?;
+        writeln!(w, "# index = x_offset y_offset top bottom middle sector")?;
+        for (i, s) in self.sidedefs.iter().enumerate() {
+            writeln!(
+                w,
+                "{i} = {} {} {} {} {} {}",
+                s.x_offset, s.y_offset, s.top, s.bottom, s.middle, s.sector
+            )?;
+        }
+        writeln!(w, "[sectors]")?;
+        writeln!(
+            w,
+            "# index = floor_height ceiling_height floor ceiling \
+            floor_light ceiling_light thing_light upper_light lower_light special tag flags"
+        )?;
+        for (i, s) in self.sectors.iter().enumerate() {
+            let [c0, c1, c2, c3, c4] = s.colors;
+            writeln!(
+                w,
+                "{i} = {} {} {} {} {c0} {c1} {c2} {c3} {c4} {} {} 0x{:x}",
+                s.floor_height, s.ceiling_height, s.floor, s.ceiling, s.special, s.tag, s.flags
+            )?;
+        }
+        write_lights_text(&self.lights, w)?;
+        if let Some(macros) = &self.macros {
+            writeln!(w, "[macros]")?;
+            writeln!(w, "specials = {}", macros.specials)?;
+            writeln!(w, "# macro.action = batch tag special")?;
+            for (i, m) in macros.macros.iter().enumerate() {
+                for (j, a) in m.actions.iter().enumerate() {
+                    writeln!(w, "{i}.{j} = {} {} {}", a.batch, a.tag, a.special)?;
+                }
+            }
+        }
+        writeln!(w, "[segs]")?;
+        writeln!(w, "# index = v1 v2 angle linedef side offset")?;
+        for (i, s) in self.segs.iter().enumerate() {
+            writeln!(
+                w,
+                "{i} = {} {} {} {} {} {}",
+                s.v1, s.v2, s.angle, s.linedef, s.side, s.offset
+            )?;
+        }
+        writeln!(w, "[subsectors]")?;
+        writeln!(w, "# index = seg_count first_seg")?;
+        for (i, s) in self.subsectors.iter().enumerate() {
+            writeln!(w, "{i} = {} {}", s.seg_count, s.first_seg)?;
+        }
+        writeln!(w, "[nodes]")?;
+        writeln!(
+            w,
+            "# index = x y dx dy right_bbox(top bottom left right) left_bbox right left"
+        )?;
+        for (i, n) in self.nodes.iter().enumerate() {
+            let bbox = n.bbox.iter().flatten().join(" ");
+            let [right, left] = n.children;
+            writeln!(
+                w,
+                "{i} = {} {} {} {} {bbox} {right} {left}",
+                n.x, n.y, n.dx, n.dy
+            )?;
+        }
+        writeln!(w, "[leafs]")?;
+        writeln!(w, "# index = vertex seg vertex seg ...")?;
+        for (i, l) in self.leafs.iter().enumerate() {
+            let vertices = l
+                .vertices
+                .iter()
+                .map(|v| format!("{} {}", v.vertex, v.seg))
+                .join(" ");
+            writeln!(w, "{i} = {vertices}")?;
+        }
+        if let Some(blockmap) = &self.blockmap {
+            writeln!(w, "[blockmap]")?;
+            writeln!(w, "origin = {} {}", blockmap.x, blockmap.y)?;
+            writeln!(w, "size = {} {}", blockmap.columns, blockmap.rows)?;
+            for (section, words) in [("offsets", &blockmap.offsets), ("lists", &blockmap.lists)] {
+                writeln!(w, "[blockmap.{section}]")?;
+                writeln!(w, "# first index = words")?;
+                for (row, chunk) in words.chunks(TEXT_ROW).enumerate() {
+                    writeln!(w, "{} = {}", row * TEXT_ROW, chunk.iter().join(" "))?;
+                }
+            }
+        }
+        writeln!(w, "[reject]")?;
+        writeln!(w, "# first byte = hex bytes")?;
+        for (row, chunk) in self.reject.chunks(TEXT_ROW).enumerate() {
+            let hex = chunk.iter().map(|b| format!("{b:02x}")).join("");
+            writeln!(w, "{} = {hex}", row * TEXT_ROW)?;
+        }
+        Ok(())
+    }
+    /// Reads a map written by [`write_text`](Self::write_text) into a map WAD. Without a
+    /// list of entries, the WAD starts with a marker called `name`.
+    pub fn parse_text(text: &str, name: &str) -> io::Result<FlatWad> {
+        let mut map = Self::default();
+        let mut entries = None;
+        for entry in parse_ini(text)? {
+            let line = entry.line;
+            let key = entry.key;
+            let mut f = Fields::new(line, entry.value);
+            match entry.section {
+                "wad" if key == "entries" => {
+                    let names = f.rest().map(|n| {
+                        EntryName::new(n).ok_or_else(|| {
+                            invalid_data(format_args!("line {line}: invalid entry name `{n}`"))
+                        })
+                    });
+                    entries = Some(names.collect::<io::Result<Vec<_>>>()?);
+                }
+                "things" => {
+                    check_index(key, line, map.things.len())?;
+                    map.things.push(Thing {
+                        x: f.int("x")?,
+                        y: f.int("y")?,
+                        z: f.int("z")?,
+                        angle: f.int("angle")?,
+                        typ: f.int("type")?,
+                        flags: f.int("flags")?,
+                        tid: f.int("tid")?,
+                    });
+                }
+                "vertexes" => {
+                    check_index(key, line, map.vertexes.len())?;
+                    map.vertexes.push(Vertex {
+                        x: f.fixed("x")?,
+                        y: f.fixed("y")?,
+                    });
+                }
+                "linedefs" => {
+                    check_index(key, line, map.linedefs.len())?;
+                    map.linedefs.push(Linedef {
+                        v1: f.int("v1")?,
+                        v2: f.int("v2")?,
+                        flags: f.int("flags")?,
+                        special: f.int("special")?,
+                        tag: f.int("tag")?,
+                        sides: [f.int("front side")?, f.int("back side")?],
+                    });
+                }
+                "sidedefs" => {
+                    check_index(key, line, map.sidedefs.len())?;
+                    map.sidedefs.push(Sidedef {
+                        x_offset: f.int("x offset")?,
+                        y_offset: f.int("y offset")?,
+                        top: f.int("top texture")?,
+                        bottom: f.int("bottom texture")?,
+                        middle: f.int("middle texture")?,
+                        sector: f.int("sector")?,
+                    });
+                }
+                "sectors" => {
+                    check_index(key, line, map.sectors.len())?;
+                    let floor_height = f.int("floor height")?;
+                    let ceiling_height = f.int("ceiling height")?;
+                    let floor = f.int("floor")?;
+                    let ceiling = f.int("ceiling")?;
+                    let mut colors = [0; 5];
+                    for color in &mut colors {
+                        *color = f.int("light")?;
+                    }
+                    map.sectors.push(Sector {
+                        floor_height,
+                        ceiling_height,
+                        floor,
+                        ceiling,
+                        colors,
+                        special: f.int("special")?,
+                        tag: f.int("tag")?,
+                        flags: f.int("flags")?,
+                    });
+                }
+                "lights" => {
+                    check_index(key, line, map.lights.len())?;
+                    map.lights.push(Light::parse_fields(&mut f)?);
+                }
+                "macros" if key == "specials" => {
+                    map.macros.get_or_insert_with(Default::default).specials = f.int("count")?;
+                }
+                "macros" => {
+                    let macros = &mut map.macros.get_or_insert_with(Default::default).macros;
+                    let (m, a) = key.split_once('.').ok_or_else(|| {
+                        invalid_data(format_args!("line {line}: expected `macro.action`"))
+                    })?;
+                    if a == "0" {
+                        check_index(m, line, macros.len())?;
+                        macros.push(Macro::default());
+                    } else {
+                        check_index(m, line, macros.len().saturating_sub(1))?;
+                    }
+                    let Some(Macro { actions }) = macros.last_mut() else {
+                        return Err(invalid_data(format_args!("line {line}: expected macro 0")));
+                    };
+                    check_index(a, line, actions.len())?;
+                    actions.push(MacroAction {
+                        batch: f.int("batch")?,
+                        tag: f.int("tag")?,
+                        special: f.int("special")?,
+                    });
+                }
+                "segs" => {
+                    check_index(key, line, map.segs.len())?;
+                    map.segs.push(Seg {
+                        v1: f.int("v1")?,
+                        v2: f.int("v2")?,
+                        angle: f.int("angle")?,
+                        linedef: f.int("linedef")?,
+                        side: f.int("side")?,
+                        offset: f.int("offset")?,
+                    });
+                }
+                "subsectors" => {
+                    check_index(key, line, map.subsectors.len())?;
+                    map.subsectors.push(Subsector {
+                        seg_count: f.int("seg count")?,
+                        first_seg: f.int("first seg")?,
+                    });
+                }
+                "nodes" => {
+                    check_index(key, line, map.nodes.len())?;
+                    let (x, y, dx, dy) = (f.int("x")?, f.int("y")?, f.int("dx")?, f.int("dy")?);
+                    let mut bbox = [[0; 4]; 2];
+                    for side in &mut bbox {
+                        for edge in side {
+                            *edge = f.int("bounding box")?;
+                        }
+                    }
+                    map.nodes.push(Node {
+                        x,
+                        y,
+                        dx,
+                        dy,
+                        bbox,
+                        children: [f.int("right child")?, f.int("left child")?],
+                    });
+                }
+                "leafs" => {
+                    check_index(key, line, map.leafs.len())?;
+                    let mut vertices = Vec::new();
+                    while let Some(vertex) = f.rest().next() {
+                        let vertex = parse_int(vertex).and_then(|v| u16::try_from(v).ok());
+                        let vertex = vertex.ok_or_else(|| {
+                            invalid_data(format_args!("line {line}: invalid vertex"))
+                        })?;
+                        vertices.push(LeafVertex {
+                            vertex,
+                            seg: f.int("seg")?,
+                        });
+                    }
+                    map.leafs.push(Leaf { vertices });
+                }
+                "blockmap" if key == "origin" => {
+                    let blockmap = map.blockmap.get_or_insert_with(Default::default);
+                    blockmap.x = f.int("x")?;
+                    blockmap.y = f.int("y")?;
+                }
+                "blockmap" if key == "size" => {
+                    let blockmap = map.blockmap.get_or_insert_with(Default::default);
+                    blockmap.columns = f.int("columns")?;
+                    blockmap.rows = f.int("rows")?;
+                }
+                "blockmap.offsets" | "blockmap.lists" => {
+                    let blockmap = map.blockmap.get_or_insert_with(Default::default);
+                    let words = if entry.section == "blockmap.offsets" {
+                        &mut blockmap.offsets
+                    } else {
+                        &mut blockmap.lists
+                    };
+                    check_index(key, line, words.len())?;
+                    while let Some(word) = f.rest().next() {
+                        let word = parse_int(word).and_then(|w| u16::try_from(w).ok());
+                        words.push(word.ok_or_else(|| {
+                            invalid_data(format_args!("line {line}: invalid blockmap word"))
+                        })?);
+                    }
+                }
+                "reject" => {
+                    check_index(key, line, map.reject.len())?;
+                    let hex = f.rest().join("");
+                    let bytes = hex.as_bytes().chunks(2).map(|b| {
+                        std::str::from_utf8(b)
+                            .ok()
+                            .filter(|b| b.len() == 2 && b.bytes().all(|c| c.is_ascii_hexdigit()))
+                            .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    });
+                    let bytes = bytes.collect::<Option<Vec<_>>>().ok_or_else(|| {
+                        invalid_data(format_args!("line {line}: invalid reject bytes"))
+                    })?;
+                    map.reject.extend(bytes);
+                }
+                section => {
+                    return Err(invalid_data(format_args!(
+                        "line {line}: unknown key `{key}` in section [{section}]"
+                    )))
+                }
+            }
+            f.end()?;
+        }
+        if let Some(blockmap) = &map.blockmap {
+            let blocks = blockmap.columns as usize * blockmap.rows as usize;
+            if blockmap.offsets.len() != blocks {
+                return Err(invalid_data(format_args!(
+                    "blockmap has {blocks} blocks but {} offsets",
+                    blockmap.offsets.len()
+                )));
+            }
+        }
+        let mut wad = FlatWad::default();
+        match entries {
+            Some(entries) => {
+                for name in entries {
+                    let typ = match LUMPS.contains(&name.display().as_ref()) {
+                        true => LumpType::MapLump,
+                        false => LumpType::Marker,
+                    };
+                    wad.entries.push(FlatEntry {
+                        name,
+                        entry: WadEntry::new(typ, Vec::new()),
+                    });
+                }
+            }
+            None => wad.entries.push(FlatEntry::marker(name)),
+        }
+        map.write(&mut wad)?;
+        Ok(wad)
+    }
+}
+
+const SCRIPT_HEADER: &str = "\
+# Doom 64 map macros written by `d64make extract --macros` and compiled by `d64make build`.
+# A linedef runs macro N when its special has the macro bit (0x100) set and N in the low
+# byte. Each action is `batch = special tag`: the actions of a batch run together, and
+# each batch starts once the one before it has finished. `end` sets the action ending a
+# macro when it isn't `0 0 0`, and `specials` overrides the action count in the header.
+";
+
+impl Macros {
+    /// Writes the macros as a script that [`parse_script`](Self::parse_script) compiles back
+    pub fn write_script(&self, w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(SCRIPT_HEADER.as_bytes())?;
+        if self.specials as usize != self.special_count() {
+            writeln!(w, "specials = {}", self.specials)?;
+        }
+        for (i, m) in self.macros.iter().enumerate() {
+            writeln!(w, "\n[macro {i}]")?;
+            let Some((end, actions)) = m.actions.split_last() else {
+                continue;
+            };
+            for a in actions {
+                writeln!(w, "{} = {} {}", a.batch, a.special, a.tag)?;
+            }
+            if actions.is_empty() || *end != MacroAction::default() {
+                writeln!(w, "end = {} {} {}", end.batch, end.special, end.tag)?;
+            }
+        }
+        Ok(())
+    }
+    /// Compiles a script written by [`write_script`](Self::write_script)
+    pub fn parse_script(text: &str) -> io::Result<Self> {
+        let mut macros = Self::default();
+        let mut specials = None;
+        let mut ends = Vec::new();
+        for entry in parse_ini(text)? {
+            let line = entry.line;
+            let mut f = Fields::new(line, entry.value);
+            if entry.section.is_empty() && entry.key == "specials" {
+                specials = Some(f.int("count")?);
+                f.end()?;
+                continue;
+            }
+            let index = entry
+                .section
+                .strip_prefix("macro")
+                .map(str::trim)
+                .ok_or_else(|| {
+                    invalid_data(format_args!("line {line}: expected a `[macro N]` section"))
+                })?;
+            let len = macros.macros.len();
+            match index.parse::<usize>().ok() {
+                Some(i) if i + 1 == len => {}
+                Some(i) if i == len => {
+                    macros.macros.push(Macro::default());
+                    ends.push(None);
+                }
+                _ => {
+                    return Err(invalid_data(format_args!(
+                        "line {line}: expected macro {len}"
+                    )))
+                }
+            }
+            let is_end = entry.key == "end";
+            let batch = if is_end {
+                f.int("batch")?
+            } else {
+                Fields::new(line, entry.key).int("batch")?
+            };
+            let action = MacroAction {
+                batch,
+                special: f.int("special")?,
+                tag: f.int("tag")?,
+            };
+            f.end()?;
+            if is_end {
+                *ends.last_mut().unwrap() = Some(action);
+            } else {
+                macros.macros.last_mut().unwrap().actions.push(action);
+            }
+        }
+        for (m, end) in macros.macros.iter_mut().zip(ends) {
+            m.actions.push(end.unwrap_or_default());
+        }
+        macros.specials = match specials {
+            Some(specials) => specials,
+            None => u16::try_from(macros.special_count())
+                .map_err(|_| invalid_data("too many actions"))?,
+        };
+        Ok(macros)
+    }
+}
+
+const LIGHTS_HEADER: &str = "\
+# Doom 64 map lights written by `d64make extract --lights text` and read by `d64make build`.
+";
+
+impl Light {
+    fn parse_fields(f: &mut Fields) -> io::Result<Self> {
+        Ok(Self {
+            r: f.int("red")?,
+            g: f.int("green")?,
+            b: f.int("blue")?,
+            a: f.int("alpha")?,
+            tag: f.int("tag")?,
+        })
+    }
+}
+
+/// Writes lights as the `[lights]` section of a text map
+fn write_lights_text(lights: &[Light], w: &mut impl io::Write) -> io::Result<()> {
+    writeln!(w, "[lights]")?;
+    writeln!(
+        w,
+        "# index = r g b a tag, referenced by sectors from {FIRST_LIGHT}"
+    )?;
+    for (i, l) in lights.iter().enumerate() {
+        writeln!(w, "{i} = {} {} {} {} {}", l.r, l.g, l.b, l.a, l.tag)?;
+    }
+    Ok(())
+}
+
+/// Writes the LIGHTS lump of a map as a standalone table in the text map format
+pub fn write_lights_table(lights: &[Light], w: &mut impl io::Write) -> io::Result<()> {
+    w.write_all(LIGHTS_HEADER.as_bytes())?;
+    write_lights_text(lights, w)
+}
+
+/// Reads a table written by [`write_lights_table`]
+pub fn parse_lights_table(text: &str) -> io::Result<Vec<Light>> {
+    let mut lights = Vec::new();
+    for entry in parse_ini(text)? {
+        let line = entry.line;
+        if entry.section != "lights" {
+            return Err(invalid_data(format_args!(
+                "line {line}: expected a `[lights]` section"
+            )));
+        }
+        check_index(entry.key, line, lights.len())?;
+        let mut f = Fields::new(line, entry.value);
+        lights.push(Light::parse_fields(&mut f)?);
+        f.end()?;
+    }
+    Ok(lights)
+}
+
+/// Writes the LIGHTS lump of a map as a PNG strip with one swatch per light
+pub fn write_lights_png(lights: &[Light]) -> io::Result<Vec<u8>> {
+    let colors = lights
+        .iter()
+        .map(|l| RGBA::new(l.r, l.g, l.b, l.a))
+        .collect::<Vec<_>>();
+    let tags = lights.iter().map(|l| l.tag).collect::<Vec<_>>();
+    gfx::write_swatch_png(&colors, &tags)
+}
+
+/// Reads a strip written by [`write_lights_png`]
+pub fn read_lights_png(data: &[u8]) -> io::Result<Vec<Light>> {
+    let (colors, tags) = gfx::read_swatch_png(data)?;
+    Ok(colors
+        .into_iter()
+        .zip(tags)
+        .map(|(c, tag)| Light {
+            r: c.r,
+            g: c.g,
+            b: c.b,
+            a: c.a,
+            tag,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lights_png() {
+        let light = |r, g, b, a, tag| Light { r, g, b, a, tag };
+        let lights = [
+            light(255, 128, 0, 255, 0),
+            light(10, 20, 30, 0, 5),
+            light(0, 0, 0, 77, -1),
+        ];
+        let png = write_lights_png(&lights).unwrap();
+        let Ok(lodepng::Image::RGBA(bitmap)) = lodepng::Decoder::new().decode(&png) else {
+            panic!("lights PNG is not RGBA");
+        };
+        assert!(bitmap.buffer.iter().all(|c| c.a == 255));
+        assert_eq!(read_lights_png(&png).unwrap(), lights);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    sound::{NoSeekWrite, PatchInfo, Sample, Sequence, SoundData},
+    sound::{Loop, NoSeekWrite, PatchInfo, Sample, Sequence, SoundData},
     too_large,
 };
 use binrw::BinRead;
@@ -11,7 +11,7 @@ use nom::{
     number::complete::{be_u16, be_u32, be_u8},
 };
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{BTreeMap, VecDeque},
     io::Cursor,
 };
 
@@ -27,10 +27,10 @@ struct SequenceBuilder {
     initqpm: u16,
     format: u16,
     tempo_map: Vec<TimedEvent>,
-    patch_changes: HashMap<u8, u16>,
-    rpns: HashMap<u8, u16>,
-    pitch_sens: HashMap<u8, (u8, u8)>,
-    tracks: HashMap<u8, Track>,
+    patch_changes: BTreeMap<u8, u16>,
+    rpns: BTreeMap<u8, u16>,
+    pitch_sens: BTreeMap<u8, (u8, u8)>,
+    tracks: BTreeMap<u8, Track>,
     cur_time: u32,
 }
 
@@ -712,7 +712,7 @@ pub struct Track {
     pub events: Vec<TimedEvent>,
 }
 
-#[derive(Copy, Clone, Debug, BinRead)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, BinRead)]
 #[br(little)]
 pub enum Event {
     #[br(magic = 7u8)]
@@ -799,7 +799,7 @@ impl Event {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TimedEvent {
     pub delta: u32,
     pub event: Event,
@@ -930,6 +930,36 @@ impl MusicSample {
             volume: sample.volume,
         }
     }
+    /// Joins the chunks back into one sample that loops over the loop chunks forever
+    pub fn to_sample(&self) -> Sample {
+        let start = self
+            .start
+            .iter()
+            .map(|info| info.samples.n_samples())
+            .sum::<usize>();
+        let data = self
+            .samples()
+            .flat_map(|info| info.samples.raw_data().into_owned())
+            .collect::<Vec<_>>();
+        let r#loop = (!self.r#loop.is_empty()).then_some(Loop {
+            start: start as u32,
+            end: data.len() as u32,
+            count: u32::MAX,
+        });
+        Sample {
+            info: PatchInfo {
+                pitch: self
+                    .samples()
+                    .next()
+                    .map(|info| info.pitch)
+                    .unwrap_or_default(),
+                samples: crate::sound::SampleData::Raw(data),
+                r#loop,
+            },
+            priority: self.priority,
+            volume: self.volume,
+        }
+    }
     pub fn to_seq(&self, patchidx: u16) -> MusicSequence {
         let mut seq = MusicSequence::default();
         let mut events = Vec::new();
@@ -0,0 +1,630 @@
+use crate::{
+    invalid_data,
+    map::{
+        Blockmap, Fixed, Leaf, LeafVertex, Map, Node, Seg, Subsector, Vertex, NODE_SUBSECTOR, NONE,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+/// Distance in map units under which a seg end counts as lying on a partition line
+const EPSILON: f64 = 1. / 128.;
+/// Distance in map units under which a corner of a leaf outline counts as lying on a seg
+const SNAP: f64 = 0.5;
+/// Cost of a partition for each seg it splits, on top of the difference in seg counts
+const SPLIT_COST: usize = 8;
+/// Width and height in map units of a blockmap block
+const BLOCK_SIZE: f64 = 128.;
+/// Space in map units between the blockmap origin and the lowest vertex
+const BLOCK_MARGIN: f64 = 8.;
+/// Space in map units around the map that leaf polygons are first clipped from
+const LEAF_MARGIN: f64 = 64.;
+
+/// Line a node divides its segs by, stored in whole map units as the engine reads it
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Partition {
+    x: i16,
+    y: i16,
+    dx: i16,
+    dy: i16,
+}
+
+impl Partition {
+    /// Signed distance of a point from the line, positive on the right (front) side
+    fn distance(&self, (x, y): (f64, f64)) -> f64 {
+        let (dx, dy) = (self.dx as f64, self.dy as f64);
+        ((x - self.x as f64) * dy - (y - self.y as f64) * dx) / dx.hypot(dy)
+    }
+}
+
+/// Side of a linedef that has not been placed in a subsector yet
+#[derive(Clone, Copy, Debug)]
+struct BuildSeg {
+    v1: u16,
+    v2: u16,
+    linedef: u16,
+    side: u16,
+}
+
+enum Side {
+    Front,
+    Back,
+    /// Crosses the partition, which it should be split at this point
+    Split(Fixed, Fixed),
+}
+
+struct Builder<'a> {
+    map: &'a mut Map,
+    vertex_indices: HashMap<(i32, i32), u16>,
+    /// Partitions from the root to the node being built, with whether the front was taken
+    path: Vec<(Partition, bool)>,
+    /// Left, bottom, right and top of the polygon leafs are clipped from
+    bounds: [f64; 4],
+}
+
+impl Builder<'_> {
+    #[inline]
+    fn point(&self, vertex: u16) -> (f64, f64) {
+        let v = &self.map.vertexes[vertex as usize];
+        (v.x.to_f64(), v.y.to_f64())
+    }
+    fn add_vertex(&mut self, x: Fixed, y: Fixed) -> io::Result<u16> {
+        if let Some(&index) = self.vertex_indices.get(&(x.0, y.0)) {
+            return Ok(index);
+        }
+        let index = self.map.vertexes.len();
+        if index >= NONE as usize {
+            return Err(invalid_data("too many vertexes"));
+        }
+        self.map.vertexes.push(Vertex { x, y });
+        self.vertex_indices.insert((x.0, y.0), index as u16);
+        Ok(index as u16)
+    }
+    /// Ends of the linedef of a seg in the direction of the seg
+    fn line_ends(&self, seg: &BuildSeg) -> (u16, u16) {
+        let line = &self.map.linedefs[seg.linedef as usize];
+        match seg.side {
+            0 => (line.v1, line.v2),
+            _ => (line.v2, line.v1),
+        }
+    }
+    /// Partition along the linedef of a seg, or `None` if it can't be stored in a node
+    fn partition(&self, seg: &BuildSeg) -> Option<Partition> {
+        let (a, b) = self.line_ends(seg);
+        let (a, b) = (self.point(a), self.point(b));
+        let (x, y) = (a.0.round() as i32, a.1.round() as i32);
+        let (dx, dy) = (b.0.round() as i32 - x, b.1.round() as i32 - y);
+        if dx == 0 && dy == 0 {
+            return None;
+        }
+        Some(Partition {
+            x: x.try_into().ok()?,
+            y: y.try_into().ok()?,
+            dx: dx.try_into().ok()?,
+            dy: dy.try_into().ok()?,
+        })
+    }
+    fn classify(&self, partition: &Partition, seg: &BuildSeg) -> Side {
+        let (a, b) = (self.point(seg.v1), self.point(seg.v2));
+        let (da, db) = (partition.distance(a), partition.distance(b));
+        if da.abs() < EPSILON && db.abs() < EPSILON {
+            let dot = (b.0 - a.0) * partition.dx as f64 + (b.1 - a.1) * partition.dy as f64;
+            return match dot > 0. {
+                true => Side::Front,
+                false => Side::Back,
+            };
+        }
+        if da > -EPSILON && db > -EPSILON {
+            return Side::Front;
+        }
+        if da < EPSILON && db < EPSILON {
+            return Side::Back;
+        }
+        let t = da / (da - db);
+        let x = Fixed::from_f64(a.0 + (b.0 - a.0) * t);
+        let y = Fixed::from_f64(a.1 + (b.1 - a.1) * t);
+        let side = |d: f64| match d > 0. {
+            true => Side::Front,
+            false => Side::Back,
+        };
+        // a split that rounds onto an end would leave a seg with no length
+        let at = |v: u16| {
+            let v = &self.map.vertexes[v as usize];
+            v.x == x && v.y == y
+        };
+        if at(seg.v1) {
+            side(db)
+        } else if at(seg.v2) {
+            side(da)
+        } else {
+            Side::Split(x, y)
+        }
+    }
+    /// Picks the partition that splits the fewest segs while keeping both sides even, or
+    /// `None` if the segs already form a convex subsector
+    fn choose_partition(&self, segs: &[BuildSeg]) -> Option<Partition> {
+        let mut seen = HashSet::new();
+        let mut best = None;
+        let mut best_cost = usize::MAX;
+        'candidates: for seg in segs {
+            let Some(partition) = self.partition(seg) else {
+                continue;
+            };
+            if !seen.insert(partition) {
+                continue;
+            }
+            let (mut front, mut back, mut splits) = (0, 0, 0);
+            for other in segs {
+                match self.classify(&partition, other) {
+                    Side::Front => front += 1,
+                    Side::Back => back += 1,
+                    Side::Split(..) => splits += 1,
+                }
+                if splits * SPLIT_COST >= best_cost {
+                    continue 'candidates;
+                }
+            }
+            if front + splits == 0 || back + splits == 0 {
+                continue;
+            }
+            let cost = splits * SPLIT_COST + usize::abs_diff(front, back);
+            if cost < best_cost {
+                best = Some(partition);
+                best_cost = cost;
+            }
+        }
+        best
+    }
+    /// Top, bottom, left and right of the ends of some segs
+    fn bbox(&self, segs: &[BuildSeg]) -> [i16; 4] {
+        let (mut left, mut right) = (f64::MAX, f64::MIN);
+        let (mut bottom, mut top) = (f64::MAX, f64::MIN);
+        for (x, y) in segs
+            .iter()
+            .flat_map(|s| [self.point(s.v1), self.point(s.v2)])
+        {
+            left = left.min(x);
+            right = right.max(x);
+            bottom = bottom.min(y);
+            top = top.max(y);
+        }
+        [top.ceil(), bottom.floor(), left.floor(), right.ceil()].map(|c| c as i16)
+    }
+    /// Builds the subtree for some segs, returning a node child reference to it
+    fn build(&mut self, segs: Vec<BuildSeg>) -> io::Result<u16> {
+        let Some(partition) = self.choose_partition(&segs) else {
+            return self.add_subsector(segs);
+        };
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        for seg in segs {
+            match self.classify(&partition, &seg) {
+                Side::Front => front.push(seg),
+                Side::Back => back.push(seg),
+                Side::Split(x, y) => {
+                    let v = self.add_vertex(x, y)?;
+                    let first = BuildSeg { v2: v, ..seg };
+                    let second = BuildSeg { v1: v, ..seg };
+                    match partition.distance(self.point(seg.v1)) > 0. {
+                        true => {
+                            front.push(first);
+                            back.push(second);
+                        }
+                        false => {
+                            back.push(first);
+                            front.push(second);
+                        }
+                    }
+                }
+            }
+        }
+        let bbox = [self.bbox(&front), self.bbox(&back)];
+        self.path.push((partition, true));
+        let right = self.build(front)?;
+        self.path.last_mut().unwrap().1 = false;
+        let left = self.build(back)?;
+        self.path.pop();
+        let index = self.map.nodes.len();
+        if index >= NODE_SUBSECTOR as usize {
+            return Err(invalid_data("too many nodes"));
+        }
+        self.map.nodes.push(Node {
+            x: partition.x,
+            y: partition.y,
+            dx: partition.dx,
+            dy: partition.dy,
+            bbox,
+            children: [right, left],
+        });
+        Ok(index as u16)
+    }
+    fn add_subsector(&mut self, segs: Vec<BuildSeg>) -> io::Result<u16> {
+        let index = self.map.subsectors.len();
+        if index >= NODE_SUBSECTOR as usize {
+            return Err(invalid_data("too many subsectors"));
+        }
+        let first = self.map.segs.len();
+        if first + segs.len() > NONE as usize {
+            return Err(invalid_data("too many segs"));
+        }
+        for seg in &segs {
+            let (start, end) = self.line_ends(seg);
+            let (start, end) = (self.point(start), self.point(end));
+            let v1 = self.point(seg.v1);
+            let angle = (end.1 - start.1).atan2(end.0 - start.0) / std::f64::consts::TAU;
+            self.map.segs.push(Seg {
+                v1: seg.v1,
+                v2: seg.v2,
+                angle: (angle * 65536.).round() as i64 as u16,
+                linedef: seg.linedef,
+                side: seg.side,
+                offset: (v1.0 - start.0).hypot(v1.1 - start.1).round() as i16,
+            });
+        }
+        self.map.subsectors.push(Subsector {
+            seg_count: segs.len() as u16,
+            first_seg: first as u16,
+        });
+        let leaf = self.leaf(&segs, first)?;
+        self.map.leafs.push(leaf);
+        Ok(index as u16 | NODE_SUBSECTOR)
+    }
+    /// Traces the outline of a subsector clockwise, giving each vertex the seg starting there
+    fn leaf(&mut self, segs: &[BuildSeg], first: usize) -> io::Result<Leaf> {
+        let [left, bottom, right, top] = self.bounds;
+        let mut polygon = vec![(left, top), (right, top), (right, bottom), (left, bottom)];
+        for &(partition, front) in &self.path {
+            polygon = clip(&polygon, |p| match front {
+                true => partition.distance(p),
+                false => -partition.distance(p),
+            });
+        }
+        for seg in segs {
+            if let Some(partition) = self.partition(seg) {
+                polygon = clip(&polygon, |p| partition.distance(p));
+            }
+        }
+
+        // the segs of a convex subsector all lie on its outline, which has more corners
+        // wherever partitions close it off without a seg
+        let corners = polygon
+            .into_iter()
+            .filter(|&p| {
+                segs.iter()
+                    .all(|s| distance_to_seg(p, self.point(s.v1), self.point(s.v2)) >= SNAP)
+            })
+            .collect::<Vec<_>>();
+        let mut outline = segs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| LeafVertex {
+                vertex: s.v1,
+                seg: (first + i) as u16,
+            })
+            .collect::<Vec<_>>();
+        let ends = segs.iter().map(|s| s.v2).collect::<Vec<_>>();
+        for vertex in ends {
+            let p = self.point(vertex);
+            if outline.iter().all(|o| self.point(o.vertex) != p) {
+                outline.push(LeafVertex { vertex, seg: NONE });
+            }
+        }
+        for (x, y) in corners {
+            let near = outline.iter().any(|o| {
+                let (ox, oy) = self.point(o.vertex);
+                (ox - x).hypot(oy - y) < SNAP
+            });
+            if !near {
+                let vertex = self.add_vertex(Fixed::from_f64(x), Fixed::from_f64(y))?;
+                outline.push(LeafVertex { vertex, seg: NONE });
+            }
+        }
+
+        let count = outline.len() as f64;
+        let (cx, cy) = outline.iter().fold((0., 0.), |(x, y), o| {
+            let p = self.point(o.vertex);
+            (x + p.0 / count, y + p.1 / count)
+        });
+        let angle = |o: &LeafVertex| {
+            let (x, y) = self.point(o.vertex);
+            (y - cy).atan2(x - cx)
+        };
+        outline.sort_by(|a, b| angle(b).total_cmp(&angle(a)));
+        Ok(Leaf { vertices: outline })
+    }
+}
+
+/// Distance from a point to the closest point on the line segment from `a` to `b`
+fn distance_to_seg(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let t = ((p.0 - a.0) * dx + (p.1 - a.1) * dy) / (dx * dx + dy * dy);
+    let t = t.clamp(0., 1.);
+    (a.0 + dx * t - p.0).hypot(a.1 + dy * t - p.1)
+}
+
+/// Cuts off the part of a convex polygon where `distance` is negative
+fn clip(polygon: &[(f64, f64)], distance: impl Fn((f64, f64)) -> f64) -> Vec<(f64, f64)> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let (da, db) = (distance(a), distance(b));
+        if da >= 0. {
+            clipped.push(a);
+        }
+        if (da > 0. && db < 0.) || (da < 0. && db > 0.) {
+            let t = da / (da - db);
+            clipped.push((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+        }
+    }
+    clipped
+}
+
+/// Whether the line from `a` to `b` passes through the square block with the given bottom
+/// left corner
+fn touches_block(a: (f64, f64), b: (f64, f64), (left, bottom): (f64, f64)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0f64, 1f64);
+    let edges = [
+        (-dx, a.0 - left),
+        (dx, left + BLOCK_SIZE - a.0),
+        (-dy, a.1 - bottom),
+        (dy, bottom + BLOCK_SIZE - a.1),
+    ];
+    for (p, q) in edges {
+        if p == 0. {
+            if q < 0. {
+                return false;
+            }
+        } else if p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    t0 <= t1
+}
+
+fn build_blockmap(map: &Map) -> io::Result<Blockmap> {
+    let point = |v: u16| {
+        let v = &map.vertexes[v as usize];
+        (v.x.to_f64(), v.y.to_f64())
+    };
+    let (mut left, mut right) = (f64::MAX, f64::MIN);
+    let (mut bottom, mut top) = (f64::MAX, f64::MIN);
+    for line in &map.linedefs {
+        for (x, y) in [point(line.v1), point(line.v2)] {
+            left = left.min(x);
+            right = right.max(x);
+            bottom = bottom.min(y);
+            top = top.max(y);
+        }
+    }
+    let x = (left - BLOCK_MARGIN).floor();
+    let y = (bottom - BLOCK_MARGIN).floor();
+    let columns = ((right - x) / BLOCK_SIZE) as usize + 1;
+    let rows = ((top - y) / BLOCK_SIZE) as usize + 1;
+    let (Ok(columns), Ok(rows)) = (u16::try_from(columns), u16::try_from(rows)) else {
+        return Err(invalid_data("map is too large for a blockmap"));
+    };
+    let (columns, rows) = (columns as usize, rows as usize);
+
+    let mut blocks = vec![Vec::new(); columns * rows];
+    for (i, line) in map.linedefs.iter().enumerate() {
+        let (a, b) = (point(line.v1), point(line.v2));
+        let block = |c: f64, origin: f64| ((c - origin) / BLOCK_SIZE) as usize;
+        for row in block(a.1.min(b.1), y)..=block(a.1.max(b.1), y) {
+            for column in block(a.0.min(b.0), x)..=block(a.0.max(b.0), x) {
+                let corner = (x + column as f64 * BLOCK_SIZE, y + row as f64 * BLOCK_SIZE);
+                if touches_block(a, b, corner) {
+                    blocks[row * columns + column].push(i as u16);
+                }
+            }
+        }
+    }
+
+    // lists start with a 0 like the ones made by the original tools, and blocks with the
+    // same lines share a list
+    let start = 4 + blocks.len();
+    let mut offsets = Vec::with_capacity(blocks.len());
+    let mut lists = Vec::new();
+    let mut shared = HashMap::new();
+    for block in blocks {
+        if let Some(&offset) = shared.get(&block) {
+            offsets.push(offset);
+            continue;
+        }
+        let offset = u16::try_from(start + lists.len())
+            .map_err(|_| invalid_data("blockmap is larger than 64K words"))?;
+        lists.push(0);
+        lists.extend_from_slice(&block);
+        lists.push(NONE);
+        shared.insert(block, offset);
+        offsets.push(offset);
+    }
+    Ok(Blockmap {
+        x: x as i16,
+        y: y as i16,
+        columns: columns as u16,
+        rows: rows as u16,
+        offsets,
+        lists,
+    })
+}
+
+/// Replaces the SEGS, SSECTORS, NODES, LEAFS, BLOCKMAP and REJECT lumps of a map with ones
+/// generated from its linedefs, sidedefs and vertexes. Vertexes no linedef uses are dropped
+/// first, so rebuilding a map again gives the same result. REJECT is left all zero, which
+/// lets every sector see every other one.
+pub fn build_nodes(map: &mut Map) -> io::Result<()> {
+    if map.linedefs.is_empty() {
+        return Err(invalid_data("map has no linedefs"));
+    }
+    let mut used = vec![false; map.vertexes.len()];
+    for (i, line) in map.linedefs.iter().enumerate() {
+        for v in [line.v1, line.v2] {
+            match used.get_mut(v as usize) {
+                Some(used) => *used = true,
+                None => {
+                    return Err(invalid_data(format_args!(
+                        "linedef {i}: vertex {v} does not exist"
+                    )))
+                }
+            }
+        }
+    }
+    let mut remap = vec![NONE; map.vertexes.len()];
+    let mut vertexes = Vec::new();
+    for (i, vertex) in std::mem::take(&mut map.vertexes).into_iter().enumerate() {
+        if used[i] {
+            remap[i] = vertexes.len() as u16;
+            vertexes.push(vertex);
+        }
+    }
+    map.vertexes = vertexes;
+    for line in &mut map.linedefs {
+        line.v1 = remap[line.v1 as usize];
+        line.v2 = remap[line.v2 as usize];
+    }
+
+    map.blockmap = Some(build_blockmap(map)?);
+    map.reject = vec![0; (map.sectors.len() * map.sectors.len()).div_ceil(8)];
+    map.segs.clear();
+    map.subsectors.clear();
+    map.nodes.clear();
+    map.leafs.clear();
+
+    let mut segs = Vec::new();
+    for (i, line) in map.linedefs.iter().enumerate() {
+        let (a, b) = (
+            &map.vertexes[line.v1 as usize],
+            &map.vertexes[line.v2 as usize],
+        );
+        if a == b {
+            log::warn!("Linedef {i} has no length and will not be drawn");
+            continue;
+        }
+        for side in 0..2 {
+            if line.sides[side] == NONE {
+                continue;
+            }
+            let (v1, v2) = match side {
+                0 => (line.v1, line.v2),
+                _ => (line.v2, line.v1),
+            };
+            segs.push(BuildSeg {
+                v1,
+                v2,
+                linedef: i as u16,
+                side: side as u16,
+            });
+        }
+    }
+
+    let blockmap = map.blockmap.as_ref().unwrap();
+    let bounds = [
+        blockmap.x as f64 - LEAF_MARGIN,
+        blockmap.y as f64 - LEAF_MARGIN,
+        blockmap.x as f64 + blockmap.columns as f64 * BLOCK_SIZE + LEAF_MARGIN,
+        blockmap.y as f64 + blockmap.rows as f64 * BLOCK_SIZE + LEAF_MARGIN,
+    ];
+    let vertex_indices = map
+        .vertexes
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, v)| ((v.x.0, v.y.0), i as u16))
+        .collect();
+    let mut builder = Builder {
+        map,
+        vertex_indices,
+        path: Vec::new(),
+        bounds,
+    };
+    builder.build(segs)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Linedef, Sector, Sidedef, LINE_TWO_SIDED};
+
+    #[test]
+    fn two_sectors() {
+        let vertex = |x, y| Vertex {
+            x: Fixed::from_int(x),
+            y: Fixed::from_int(y),
+        };
+        let line = |v1, v2, front, back| Linedef {
+            v1,
+            v2,
+            flags: if back == NONE { 0 } else { LINE_TWO_SIDED },
+            sides: [front, back],
+            ..Default::default()
+        };
+        let side = |sector| Sidedef {
+            sector,
+            ..Default::default()
+        };
+        // a 256x128 room split in two down the middle, plus a vertex no linedef uses
+        let mut map = Map {
+            vertexes: vec![
+                vertex(0, 0),
+                vertex(128, 0),
+                vertex(256, 0),
+                vertex(256, 128),
+                vertex(128, 128),
+                vertex(0, 128),
+                vertex(64, 64),
+            ],
+            linedefs: vec![
+                line(0, 5, 0, NONE),
+                line(5, 4, 1, NONE),
+                line(1, 0, 2, NONE),
+                line(4, 1, 3, 4),
+                line(4, 3, 5, NONE),
+                line(3, 2, 6, NONE),
+                line(2, 1, 7, NONE),
+            ],
+            sidedefs: vec![
+                side(0),
+                side(0),
+                side(0),
+                side(0),
+                side(1),
+                side(1),
+                side(1),
+                side(1),
+            ],
+            sectors: vec![
+                Sector {
+                    ceiling_height: 128,
+                    ..Default::default()
+                };
+                2
+            ],
+            ..Default::default()
+        };
+        build_nodes(&mut map).unwrap();
+
+        assert_eq!(map.validate(1), Vec::<String>::new());
+        assert_eq!(map.vertexes.len(), 6);
+        assert!(!map.subsectors.is_empty());
+        assert_eq!(map.leafs.len(), map.subsectors.len());
+        assert_eq!(map.reject, [0]);
+        for (i, seg) in map.segs.iter().enumerate() {
+            let line = &map.linedefs[seg.linedef as usize];
+            let point = |v: u16| {
+                let v = &map.vertexes[v as usize];
+                (v.x.0 as i64, v.y.0 as i64)
+            };
+            let (a, b) = (point(line.v1), point(line.v2));
+            for v in [seg.v1, seg.v2] {
+                let p = point(v);
+                let cross = (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+                assert_eq!(cross, 0, "seg {i} is off linedef {}", seg.linedef);
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    extract::is_archive_path,
+    invalid_data,
+    project::{relative_name, PROJECT_FILES},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Project directory to pack
+    input: PathBuf,
+    /// PK3/ZIP archive to output to
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Relative archive path of a file, always using `/` as the separator
+fn archive_name(root: &Path, path: &Path) -> io::Result<String> {
+    relative_name(root, path)
+        .ok_or_else(|| invalid_data(format_args!("Invalid file name `{}`", path.display())))
+}
+
+pub fn pack(args: Args) -> io::Result<()> {
+    let Args { input, output } = args;
+    if !input.is_dir() {
+        return Err(invalid_data(format_args!(
+            "`{}` is not a directory",
+            input.display()
+        )));
+    }
+    if !is_archive_path(&output) {
+        return Err(invalid_data(format_args!(
+            "`{}` must have a .pk3 or .zip extension",
+            output.display()
+        )));
+    }
+
+    log::info!("Checking `{}`", input.display());
+    let mut files = crate::build::check_dir(&input)?;
+    files.extend(
+        PROJECT_FILES
+            .iter()
+            .map(|name| input.join(name))
+            .filter(|path| path.is_file()),
+    );
+    let mut files = files
+        .iter()
+        .map(|path| Ok((archive_name(&input, path)?, path)))
+        .collect::<io::Result<Vec<_>>>()?;
+    files.sort();
+
+    log::info!("Writing `{}`", output.display());
+    let mut archive = zip::ZipWriter::new(BufWriter::new(File::create(&output)?));
+    for (name, path) in &files {
+        log::debug!("packing `{name}`");
+        archive
+            .start_file(name.as_str(), zip::write::FileOptions::default())
+            .map_err(invalid_data)?;
+        io::copy(&mut File::open(path)?, &mut archive)?;
+    }
+    archive.finish().map_err(invalid_data)?.flush()?;
+    log::info!("Packed {} files", files.len());
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use crate::{invalid_data, lock::LOCK_NAME, EntryName};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// File name of the manifest at the root of a project directory
+pub const MANIFEST_NAME: &str = "d64make.ini";
+
+/// Folders recognized when loading a project directory or archive
+pub const PROJECT_DIRS: &[&str] = &[
+    "SPRITES", "PALETTES", "TEXTURES", "FLATS", "GRAPHICS", "HUD", "SKIES", "MAPS", "MACROS",
+    "LIGHTS", "SOUNDS", "MUSIC", "DEMOS",
+];
+
+/// Files at the root of a project that describe it and are never loaded as lumps
+pub const PROJECT_FILES: &[&str] = &[MANIFEST_NAME, LOCK_NAME, "README.md"];
+
+pub fn is_project_file(name: &OsStr) -> bool {
+    PROJECT_FILES.iter().any(|f| name.eq_ignore_ascii_case(f))
+}
+
+/// Path of a file inside a project or archive, always using `/` as the separator
+pub fn relative_name(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    let parts = rel
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub name: String,
+    pub base: Option<PathBuf>,
+    /// Uppercase file names longer than an entry name, mapped to the entry they hold
+    pub aliases: BTreeMap<String, EntryName>,
+    pub map_table: MapTable,
+}
+
+/// Numbers and names replaced when converting maps from PC formats, from the `[things]`,
+/// `[specials]`, `[sectors]` and `[textures]` sections of the manifest. Anything missing
+/// from a section is kept as it is.
+#[derive(Clone, Debug, Default)]
+pub struct MapTable {
+    pub things: BTreeMap<i16, i16>,
+    /// Linedef specials, including the Doom 64 trigger and key bits of the replacement
+    pub specials: BTreeMap<u16, u16>,
+    pub sectors: BTreeMap<i16, i16>,
+    /// Uppercase PC texture and flat names, mapped to the Doom 64 ones they become
+    pub textures: BTreeMap<String, EntryName>,
+}
+
+/// A single `key = value` line from an INI file
+#[derive(Debug)]
+pub struct IniEntry<'a> {
+    pub line: usize,
+    pub section: &'a str,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+/// Minimal INI reader: `[section]` headers, `key = value` pairs and `#`/`;` comments
+pub fn parse_ini(text: &str) -> io::Result<Vec<IniEntry<'_>>> {
+    let mut section = "";
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            section = name
+                .strip_suffix(']')
+                .ok_or_else(|| invalid_data(format_args!("line {line_no}: unterminated section")))?
+                .trim();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid_data(format_args!("line {line_no}: expected `key = value`")))?;
+        entries.push(IniEntry {
+            line: line_no,
+            section,
+            key: key.trim(),
+            value: value.trim(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Number in decimal or with a `0x` prefix in hexadecimal
+fn parse_number<T: TryFrom<i64>>(line: usize, s: &str) -> io::Result<T> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    n.ok()
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| invalid_data(format_args!("line {line}: `{s}` is not a valid number")))
+}
+
+/// Key and value of a line mapping one number to another
+fn parse_pair<K: TryFrom<i64>, V: TryFrom<i64>>(entry: &IniEntry) -> io::Result<(K, V)> {
+    let key = parse_number(entry.line, entry.key)?;
+    Ok((key, parse_number(entry.line, entry.value)?))
+}
+
+fn parse_entry_name(entry: &IniEntry) -> io::Result<EntryName> {
+    EntryName::new(&entry.value.to_ascii_uppercase()).ok_or_else(|| {
+        invalid_data(format_args!(
+            "line {}: `{}` is not a valid entry name",
+            entry.line, entry.value
+        ))
+    })
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut manifest = Self::default();
+        let table = &mut manifest.map_table;
+        for entry in parse_ini(text)? {
+            match (entry.section, entry.key) {
+                ("project", "name") => manifest.name = entry.value.to_owned(),
+                ("project", "base") => manifest.base = Some(PathBuf::from(entry.value)),
+                ("aliases", file) => {
+                    let name = parse_entry_name(&entry)?;
+                    manifest.aliases.insert(file.to_ascii_uppercase(), name);
+                }
+                ("things", _) => {
+                    let (from, to) = parse_pair(&entry)?;
+                    table.things.insert(from, to);
+                }
+                ("specials", _) => {
+                    let (from, to) = parse_pair(&entry)?;
+                    table.specials.insert(from, to);
+                }
+                ("sectors", _) => {
+                    let (from, to) = parse_pair(&entry)?;
+                    table.sectors.insert(from, to);
+                }
+                ("textures", name) => {
+                    let to = parse_entry_name(&entry)?;
+                    table.textures.insert(name.to_ascii_uppercase(), to);
+                }
+                _ => log::warn!(
+                    "{MANIFEST_NAME}:{}: unknown key `{}` in section [{}]",
+                    entry.line,
+                    entry.key,
+                    entry.section
+                ),
+            }
+        }
+        Ok(manifest)
+    }
+    /// Reads the manifest in a project directory, if there is one
+    pub fn read(dir: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let path = dir.as_ref().join(MANIFEST_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text)
+                .map(Some)
+                .map_err(|e| invalid_data(format_args!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        writeln!(w, "# d64make project manifest")?;
+        writeln!(w, "[project]")?;
+        writeln!(w, "name = {}", self.name)?;
+        if let Some(base) = &self.base {
+            writeln!(w, "base = {}", base.display())?;
+        }
+        if !self.aliases.is_empty() {
+            writeln!(w, "[aliases]")?;
+            for (file, name) in &self.aliases {
+                writeln!(w, "{file} = {}", name.display())?;
+            }
+        }
+        self.map_table.write(w)
+    }
+}
+
+impl MapTable {
+    fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        fn section<K: std::fmt::Display, V: std::fmt::Display>(
+            w: &mut impl io::Write,
+            name: &str,
+            entries: &BTreeMap<K, V>,
+        ) -> io::Result<()> {
+            if !entries.is_empty() {
+                writeln!(w, "[{name}]")?;
+                for (from, to) in entries {
+                    writeln!(w, "{from} = {to}")?;
+                }
+            }
+            Ok(())
+        }
+        section(w, "things", &self.things)?;
+        section(w, "specials", &self.specials)?;
+        section(w, "sectors", &self.sectors)?;
+        let textures = self
+            .textures
+            .iter()
+            .map(|(from, to)| (from, to.display()))
+            .collect();
+        section(w, "textures", &textures)
+    }
+}
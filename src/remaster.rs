@@ -6,9 +6,10 @@ use crate::{
     convert_error,
     extract::WadType,
     invalid_data,
+    map::Map,
     sound::{Loop, SoundData},
     wad::{FlatWad, LumpType},
-    FileFilters,
+    EntryName, FileFilters, FlatEntry,
 };
 
 pub const REMASTER_WAD_HASH: [u8; 32] =
@@ -42,6 +43,91 @@ fn unhash_texture(tex: &mut [u8], hashes: &HashMap<u16, u16>) {
     tex.copy_from_slice(&id.to_le_bytes());
 }
 
+/// Writes a map WAD in the remaster format, with every lump uncompressed and the texture
+/// indices of sectors and sidedefs replaced by hashes of the names in `textures`
+pub fn write_map(
+    wad: &FlatWad,
+    textures: &[EntryName],
+    w: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    let mut map = Map::read(wad)?;
+    for (index, record, i) in map_texture_fields(&mut map) {
+        let name = textures.get(*index as usize).ok_or_else(|| {
+            invalid_data(format_args!(
+                "{record} {i} uses texture {index}, but there are only {} textures",
+                textures.len()
+            ))
+        })?;
+        *index = hash_texture_name(&name.0);
+    }
+    let mut out = FlatWad::default();
+    for entry in &wad.entries {
+        out.entries.push(FlatEntry {
+            name: entry.name.clone(),
+            entry: entry.entry.decompress()?.into_owned(),
+        });
+    }
+    map.write(&mut out)?;
+    out.write(w, false)
+}
+
+/// Hashes of texture names, mapped to the index of the first texture with each hash
+pub fn texture_hashes<'a>(textures: impl IntoIterator<Item = &'a EntryName>) -> HashMap<u16, u16> {
+    let mut hashes = HashMap::new();
+    for (index, name) in textures.into_iter().enumerate() {
+        hashes
+            .entry(hash_texture_name(&name.0))
+            .or_insert(index as u16);
+    }
+    hashes
+}
+
+fn map_texture_fields(map: &mut Map) -> impl Iterator<Item = (&mut u16, &'static str, usize)> {
+    let sectors = map
+        .sectors
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(i, s)| [(&mut s.floor, "sector", i), (&mut s.ceiling, "sector", i)]);
+    let sides = map.sidedefs.iter_mut().enumerate().flat_map(|(i, s)| {
+        [
+            (&mut s.top, "sidedef", i),
+            (&mut s.bottom, "sidedef", i),
+            (&mut s.middle, "sidedef", i),
+        ]
+    });
+    sectors.chain(sides)
+}
+
+/// Replaces texture name hashes in a map saved in the remaster format with the indices
+/// `hashes` maps them to. Maps are taken to be in the remaster format when they refer to
+/// textures past the `textures` being built and most of those references are known hashes.
+/// Returns `false` without changing anything for other maps.
+pub fn unhash_map(
+    map: &mut Map,
+    textures: usize,
+    hashes: &HashMap<u16, u16>,
+) -> std::io::Result<bool> {
+    let (mut out_of_range, mut known) = (0usize, 0usize);
+    for (tex, _, _) in map_texture_fields(map) {
+        if *tex as usize >= textures {
+            out_of_range += 1;
+            known += hashes.contains_key(tex) as usize;
+        }
+    }
+    if out_of_range == 0 || known * 2 <= out_of_range {
+        return Ok(false);
+    }
+    for (tex, record, i) in map_texture_fields(map) {
+        let index = *hashes.get(tex).ok_or_else(|| {
+            invalid_data(format_args!(
+                "{record} {i} uses texture hash {tex}, which matches no texture being built"
+            ))
+        })?;
+        *tex = index;
+    }
+    Ok(true)
+}
+
 /// Convert the 2020 remaster WAD back to N64 format
 pub fn read_wad(
     data: &[u8],
@@ -0,0 +1,348 @@
+use std::{collections::HashMap, io, path::PathBuf};
+
+use crate::{
+    extract::{self, ReadFlags},
+    gfx::{self, RGBA},
+    invalid_data,
+    map::{self, Map, ThingKind},
+    FlatWad, LumpType,
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// ROM or IWAD file to read the map from
+    input: PathBuf,
+    /// Name of the map entry, like MAP01
+    map: String,
+    /// Output PNG file [default: <MAP>.png]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Length in pixels of the longer side of the image
+    #[arg(long, default_value_t = 1024)]
+    size: u32,
+    /// Fill sectors with their floor flat, shaded by the floor color
+    #[arg(long, default_value_t = false)]
+    flats: bool,
+    /// Don't draw things
+    #[arg(long, default_value_t = false)]
+    no_things: bool,
+}
+
+const MARGIN: f64 = 8.;
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 255);
+
+/// RGBA image with a transform from map coordinates to pixels
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<RGBA>,
+    left: f64,
+    top: f64,
+    scale: f64,
+}
+
+impl Canvas {
+    fn new(map: &Map, size: u32) -> io::Result<Self> {
+        let (mut left, mut right) = (f64::MAX, f64::MIN);
+        let (mut bottom, mut top) = (f64::MAX, f64::MIN);
+        for v in &map.vertexes {
+            let (x, y) = (v.x.to_f64(), v.y.to_f64());
+            left = left.min(x);
+            right = right.max(x);
+            bottom = bottom.min(y);
+            top = top.max(y);
+        }
+        if left > right {
+            return Err(invalid_data("map has no vertexes"));
+        }
+        let size = size as f64;
+        let extent = (right - left).max(top - bottom).max(1.);
+        let scale = (size - MARGIN * 2.).max(1.) / extent;
+        let width = ((right - left) * scale + MARGIN * 2.).ceil() as usize;
+        let height = ((top - bottom) * scale + MARGIN * 2.).ceil() as usize;
+        Ok(Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+            left,
+            top,
+            scale,
+        })
+    }
+    #[inline]
+    fn to_pixel(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - self.left) * self.scale + MARGIN,
+            (self.top - y) * self.scale + MARGIN,
+        )
+    }
+    #[inline]
+    fn to_map(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - MARGIN) / self.scale + self.left,
+            self.top - (y - MARGIN) / self.scale,
+        )
+    }
+    #[inline]
+    fn plot(&mut self, x: i64, y: i64, color: RGBA) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+    }
+    fn line(&mut self, (x0, y0): (f64, f64), (x1, y1): (f64, f64), color: RGBA) {
+        let (mut x0, mut y0) = (x0.round() as i64, y0.round() as i64);
+        let (x1, y1) = (x1.round() as i64, y1.round() as i64);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.plot(x0, y0, color);
+            if x0 == x1 && y0 == y1 {
+                break;
+            }
+            let e2 = err * 2;
+            if e2 >= dy {
+                err += dy;
+                x0 += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y0 += sy;
+            }
+        }
+    }
+    /// Fills a convex polygon given in pixel coordinates, calling `color` with the map
+    /// coordinates of each pixel center
+    fn fill(&mut self, points: &[(f64, f64)], mut color: impl FnMut(f64, f64) -> RGBA) {
+        let top = points.iter().map(|p| p.1).fold(f64::MAX, f64::min);
+        let bottom = points.iter().map(|p| p.1).fold(f64::MIN, f64::max);
+        let first_row = (top - 0.5).ceil().max(0.) as usize;
+        let last_row = ((bottom - 0.5).floor() as i64).min(self.height as i64 - 1);
+        for row in first_row as i64..=last_row {
+            let cy = row as f64 + 0.5;
+            let (mut start, mut end) = (f64::MAX, f64::MIN);
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                if (y0 <= cy) != (y1 <= cy) {
+                    let x = x0 + (cy - y0) * (x1 - x0) / (y1 - y0);
+                    start = start.min(x);
+                    end = end.max(x);
+                }
+            }
+            if start > end {
+                continue;
+            }
+            let first = (start - 0.5).ceil().max(0.) as i64;
+            let last = ((end - 0.5).floor() as i64).min(self.width as i64 - 1);
+            for column in first..=last {
+                let (x, y) = self.to_map(column as f64 + 0.5, cy);
+                let c = color(x, y);
+                self.plot(column, row, c);
+            }
+        }
+    }
+}
+
+/// Color of a sector color index, which is either a grey shade or an entry in LIGHTS
+fn light_color(map: &Map, index: u16) -> [u8; 3] {
+    match index.checked_sub(map::FIRST_LIGHT) {
+        None => [index as u8; 3],
+        Some(i) => map
+            .lights
+            .get(i as usize)
+            .map(|l| [l.r, l.g, l.b])
+            .unwrap_or([255; 3]),
+    }
+}
+
+fn line_color(line: &map::Linedef) -> RGBA {
+    if line.flags & map::LINE_DONT_DRAW != 0 {
+        return RGBA::new(64, 64, 64, 255);
+    }
+    if line.special & map::SPECIAL_RED_KEY != 0 {
+        return RGBA::new(255, 32, 32, 255);
+    }
+    if line.special & map::SPECIAL_BLUE_KEY != 0 {
+        return RGBA::new(64, 96, 255, 255);
+    }
+    if line.special & map::SPECIAL_YELLOW_KEY != 0 {
+        return RGBA::new(255, 224, 0, 255);
+    }
+    if line.special & map::SPECIAL_MACRO != 0 {
+        return RGBA::new(224, 64, 224, 255);
+    }
+    if line.action() != 0 {
+        return RGBA::new(64, 224, 64, 255);
+    }
+    if line.flags & map::LINE_SECRET != 0 {
+        return RGBA::new(0, 192, 192, 255);
+    }
+    if line.sides[1] == map::NONE || line.flags & map::LINE_TWO_SIDED == 0 {
+        RGBA::new(255, 255, 255, 255)
+    } else {
+        RGBA::new(128, 128, 128, 255)
+    }
+}
+
+fn thing_color(thing: &map::Thing) -> RGBA {
+    match thing.kind() {
+        ThingKind::Player => RGBA::new(0, 255, 0, 255),
+        ThingKind::Monster => RGBA::new(255, 0, 0, 255),
+        ThingKind::Key => RGBA::new(255, 0, 255, 255),
+        ThingKind::Item => RGBA::new(255, 255, 0, 255),
+        ThingKind::Other => RGBA::new(160, 112, 64, 255),
+    }
+}
+
+/// Decodes the flats used by the floors of a map, keyed by their index after `T_START`
+fn load_flats(iwad: &FlatWad, map: &Map) -> HashMap<u16, gfx::Texture> {
+    let start = iwad
+        .entries
+        .iter()
+        .position(|e| e.name.0.as_slice() == b"T_START");
+    let mut flats = HashMap::new();
+    let Some(start) = start else {
+        log::warn!("IWAD has no T_START, sectors will not be filled");
+        return flats;
+    };
+    for sector in &map.sectors {
+        if flats.contains_key(&sector.floor) {
+            continue;
+        }
+        let Some(entry) = iwad.entries.get(start + 1 + sector.floor as usize) else {
+            continue;
+        };
+        if !matches!(entry.entry.typ, LumpType::Flat | LumpType::Texture) {
+            continue;
+        }
+        let texture = entry
+            .entry
+            .decompress()
+            .ok()
+            .and_then(|e| gfx::Texture::parse::<()>(&e.data).ok().map(|t| t.1));
+        match texture {
+            Some(texture) => {
+                flats.insert(sector.floor, texture);
+            }
+            None => log::warn!("Failed to decode flat `{}`", entry.name.display()),
+        }
+    }
+    flats
+}
+
+/// Returns the sector of a subsector through its first seg
+fn subsector_sector(map: &Map, index: usize) -> Option<&map::Sector> {
+    let subsector = map.subsectors.get(index)?;
+    let seg = map.segs.get(subsector.first_seg as usize)?;
+    let line = map.linedefs.get(seg.linedef as usize)?;
+    let side = map
+        .sidedefs
+        .get(*line.sides.get(seg.side as usize)? as usize)?;
+    map.sectors.get(side.sector as usize)
+}
+
+fn fill_flats(canvas: &mut Canvas, iwad: &FlatWad, map: &Map) {
+    if map.leafs.is_empty() {
+        log::warn!("Map has no LEAFS, sectors will not be filled");
+        return;
+    }
+    let flats = load_flats(iwad, map);
+    for (index, leaf) in map.leafs.iter().enumerate() {
+        let Some(sector) = subsector_sector(map, index) else {
+            continue;
+        };
+        let Some(flat) = flats.get(&sector.floor) else {
+            continue;
+        };
+        let points = leaf
+            .vertices
+            .iter()
+            .filter_map(|v| map.vertexes.get(v.vertex as usize))
+            .map(|v| canvas.to_pixel(v.x.to_f64(), v.y.to_f64()))
+            .collect::<Vec<_>>();
+        if points.len() < 3 {
+            continue;
+        }
+        let shade = light_color(map, sector.colors[0]);
+        canvas.fill(&points, |x, y| {
+            let c = flat.pixel(x.floor() as i32, -y.floor() as i32);
+            let mul = |c: u8, s: u8| (c as u16 * s as u16 / 255) as u8;
+            RGBA::new(
+                mul(c.r, shade[0]),
+                mul(c.g, shade[1]),
+                mul(c.b, shade[2]),
+                255,
+            )
+        });
+    }
+}
+
+pub fn render_map(args: Args) -> io::Result<()> {
+    let Args {
+        input,
+        map: name,
+        output,
+        size,
+        flats,
+        no_things,
+    } = args;
+    let name = name.to_ascii_uppercase();
+    let paths = extract::ReadPaths::default();
+    let (iwad, _) =
+        extract::read_rom_or_iwad(&input, ReadFlags::IWAD | ReadFlags::DECOMPRESS, &paths)?;
+    let iwad = iwad.unwrap();
+    let entry = iwad
+        .entries
+        .iter()
+        .find(|e| e.entry.typ == LumpType::Map && e.name.display() == name)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("map `{name}` not found in `{}`", input.display()),
+            )
+        })?;
+    let map = Map::read(&map::read_map_wad(&entry.entry)?)?;
+
+    let mut canvas = Canvas::new(&map, size)?;
+    if flats {
+        fill_flats(&mut canvas, &iwad, &map);
+    }
+    for line in &map.linedefs {
+        let (Some(v1), Some(v2)) = (
+            map.vertexes.get(line.v1 as usize),
+            map.vertexes.get(line.v2 as usize),
+        ) else {
+            continue;
+        };
+        let p1 = canvas.to_pixel(v1.x.to_f64(), v1.y.to_f64());
+        let p2 = canvas.to_pixel(v2.x.to_f64(), v2.y.to_f64());
+        canvas.line(p1, p2, line_color(line));
+    }
+    if !no_things {
+        let radius = (canvas.scale * 8.).clamp(1., 6.).round() as i64;
+        for thing in &map.things {
+            let (x, y) = canvas.to_pixel(thing.x as f64, thing.y as f64);
+            let (x, y) = (x.round() as i64, y.round() as i64);
+            let color = thing_color(thing);
+            for py in y - radius..=y + radius {
+                for px in x - radius..=x + radius {
+                    canvas.plot(px, py, color);
+                }
+            }
+        }
+    }
+
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{name}.png")));
+    let png = lodepng::Encoder::new()
+        .encode(&canvas.pixels, canvas.width, canvas.height)
+        .map_err(invalid_data)?;
+    log::info!(
+        "Writing {}x{} image to `{}`",
+        canvas.width,
+        canvas.height,
+        output.display()
+    );
+    std::fs::write(output, png)
+}
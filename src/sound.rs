@@ -298,6 +298,9 @@ where
 }
 
 impl Sample {
+    pub const DEFAULT_PRIORITY: u8 = 50;
+    pub const DEFAULT_VOLUME: u8 = 127;
+
     pub fn read_file(data: &[u8]) -> std::io::Result<Self> {
         let magic = data.get(0..4);
         if magic == Some(b"RIFF") {
@@ -334,11 +337,11 @@ impl Sample {
         let priority = parse_flac_tag(&r, "d64_priority")
             .or_else(|| parse_flac_tag(&r, "priority"))
             .transpose()?
-            .unwrap_or(50);
+            .unwrap_or(Self::DEFAULT_PRIORITY);
         let volume = parse_flac_tag(&r, "d64_volume")
             .or_else(|| parse_flac_tag(&r, "volume"))
             .transpose()?
-            .unwrap_or(127);
+            .unwrap_or(Self::DEFAULT_VOLUME);
         let mut r#loop = None;
         if let Some(start) = parse_flac_tag::<u32>(&r, "d64_loop")
             .or_else(|| parse_flac_tag(&r, "loop"))
@@ -371,8 +374,8 @@ impl Sample {
     ) -> nom::IResult<&'a [u8], Self, E> {
         let mut samplesize = 0;
         let mut samplerate = 0;
-        let mut volume = 127;
-        let mut priority = 50;
+        let mut volume = Self::DEFAULT_VOLUME;
+        let mut priority = Self::DEFAULT_PRIORITY;
         let mut samples = None;
         let mut r#loop = None;
         let (data, _) = parse_riff_header(data, b"WAVE")?;
@@ -836,6 +839,7 @@ impl SoundData {
             .last_key_value()
             .map(|e| *e.0 + 1)
             .unwrap_or_default();
+        // only used to find shared samples, ids are handed out in instrument order
         let mut samples_written = HashMap::new();
         let mut patch_count = 0usize;
         let mut patchmap_count = 0usize;
@@ -1259,3 +1263,67 @@ where
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Instruments sharing samples, so the WMD has to dedupe them
+    fn shared_samples() -> SoundData {
+        let sample = |len, pitch| {
+            Rc::new(RefCell::new(PatchInfo {
+                samples: SampleData::Raw(vec![pitch as i16; len]),
+                pitch,
+                r#loop: None,
+            }))
+        };
+        let (a, b, c) = (sample(8, 1), sample(16, 2), sample(24, 3));
+        let instrument = |samples: &[&Rc<RefCell<PatchInfo>>]| Instrument {
+            patchmaps: samples
+                .iter()
+                .map(|&sample| PatchMap {
+                    sample: Some(sample.clone()),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        SoundData {
+            instruments: BTreeMap::from([
+                (0, instrument(&[&b, &a])),
+                (1, instrument(&[&a])),
+                (2, instrument(&[&c, &b])),
+            ]),
+            sequences: BTreeMap::new(),
+        }
+    }
+
+    fn write(snd: &SoundData) -> (Vec<u8>, Vec<u8>) {
+        let (mut wmd, mut wdd) = (Vec::new(), Vec::new());
+        snd.write_wmd(&mut wmd).unwrap();
+        snd.write_wdd(&mut wdd).unwrap();
+        (wmd, wdd)
+    }
+
+    #[test]
+    fn wmd_sample_order() {
+        let (wmd, wdd) = write(&shared_samples());
+        for _ in 0..8 {
+            assert!(write(&shared_samples()) == (wmd.clone(), wdd.clone()));
+        }
+        let instruments = extract_instruments::<nom::error::VerboseError<_>>(&wmd, &wdd, false)
+            .unwrap()
+            .1;
+        let samples = instruments
+            .values()
+            .flat_map(|inst| &inst.patchmaps)
+            .map(|map| {
+                let sample = map.sample.as_ref().unwrap().borrow();
+                (map.sample_id, sample.pitch, sample.samples.stored_len())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [(0, 2, 32), (1, 1, 16), (1, 1, 16), (2, 3, 48), (0, 2, 32)]
+        );
+    }
+}
@@ -0,0 +1,561 @@
+use crate::{
+    extract::{self, PaletteCache, ReadFlags, ReadPaths, WadType},
+    invalid_data,
+    map::Map,
+    music::MusicSequence,
+    sound::{Instrument, PatchInfo, Sample, SampleData, Sequence, SoundData},
+    FlatWad, LumpType,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// ROM or IWAD to verify
+    #[arg(required_unless_present = "manifest")]
+    input: Option<PathBuf>,
+    /// Check output files against a hash manifest written by `build --manifest`
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Directory to extract and build in, which is kept afterwards [default: a temporary directory]
+    #[arg(long)]
+    workdir: Option<PathBuf>,
+    /// Keep the temporary directory after verifying
+    #[arg(long, default_value_t = false)]
+    keep: bool,
+    /// Optional WDD file to read when verifying IWAD [default: DOOM64.WDD]
+    #[arg(long)]
+    wdd: Option<PathBuf>,
+    /// Optional WMD file to read when verifying IWAD [default: DOOM64.WMD]
+    #[arg(long)]
+    wmd: Option<PathBuf>,
+    /// Optional WSD file to read when verifying IWAD [default: DOOM64.WSD]
+    #[arg(long)]
+    wsd: Option<PathBuf>,
+    /// Optional DLS file to read when verifying remaster IWAD [default: DOOMSND.DLS]
+    #[arg(long)]
+    dls: Option<PathBuf>,
+}
+
+/// Re-encoded audio at or above this signal-to-noise ratio counts as lossy rather than failed
+const MIN_SNR_DB: f64 = 20.0;
+
+/// Number of samples in one ADPCM frame, which lengths may be rounded up to
+const ADPCM_FRAME: usize = 16;
+
+enum Outcome {
+    Pass,
+    Lossy(String),
+    Fail(String),
+}
+
+#[derive(Default)]
+struct Report {
+    passed: usize,
+    lossy: usize,
+    failed: usize,
+}
+
+impl Report {
+    fn add(&mut self, kind: &str, name: impl std::fmt::Display, outcome: Outcome) {
+        let (status, detail) = match outcome {
+            Outcome::Pass => {
+                self.passed += 1;
+                ("PASS ", String::new())
+            }
+            Outcome::Lossy(detail) => {
+                self.lossy += 1;
+                ("LOSSY", detail)
+            }
+            Outcome::Fail(detail) => {
+                self.failed += 1;
+                ("FAIL ", detail)
+            }
+        };
+        let name = name.to_string();
+        log::info!("  {status} {kind: <10} {name: <8} {detail}");
+    }
+}
+
+/// Differences found while comparing one asset
+#[derive(Default)]
+struct Diff {
+    fail: Vec<String>,
+    lossy: Vec<String>,
+}
+
+impl Diff {
+    fn field<T: PartialEq + Debug>(&mut self, what: impl std::fmt::Display, a: T, b: T) {
+        if a != b {
+            self.fail.push(format!("{what} {a:?} != {b:?}"));
+        }
+    }
+    fn outcome(self) -> Outcome {
+        if !self.fail.is_empty() {
+            Outcome::Fail(self.fail.join("; "))
+        } else if !self.lossy.is_empty() {
+            Outcome::Lossy(self.lossy.join("; "))
+        } else {
+            Outcome::Pass
+        }
+    }
+}
+
+/// Signal-to-noise ratio of `b` against `a` in decibels
+fn snr(a: &[i16], b: &[i16]) -> f64 {
+    let mut signal = 0f64;
+    let mut noise = 0f64;
+    for (&a, &b) in a.iter().zip(b) {
+        signal += (a as f64).powi(2);
+        noise += (a as f64 - b as f64).powi(2);
+    }
+    if noise == 0. {
+        return f64::INFINITY;
+    }
+    10. * (signal / noise).log10()
+}
+
+fn compare_sample(diff: &mut Diff, prefix: &str, a: &PatchInfo, b: &PatchInfo) {
+    diff.field(format_args!("{prefix}pitch"), a.pitch, b.pitch);
+    diff.field(format_args!("{prefix}loop"), &a.r#loop, &b.r#loop);
+    let (alen, blen) = (a.samples.n_samples(), b.samples.n_samples());
+    if alen.abs_diff(blen) >= ADPCM_FRAME {
+        diff.field(format_args!("{prefix}length"), alen, blen);
+    } else if alen != blen {
+        diff.lossy.push(format!("{prefix}length {alen} != {blen}"));
+    }
+    match (&a.samples, &b.samples) {
+        (SampleData::Raw(a), SampleData::Raw(b)) if a == b => return,
+        (
+            SampleData::Adpcm {
+                data: adata,
+                book: abook,
+                ..
+            },
+            SampleData::Adpcm {
+                data: bdata,
+                book: bbook,
+                ..
+            },
+        ) => {
+            let same_book = abook.order == bbook.order
+                && abook.npredictors == bbook.npredictors
+                && abook.book == bbook.book;
+            if same_book && adata == bdata {
+                return;
+            }
+            if !same_book {
+                diff.lossy.push(format!("{prefix}ADPCM book re-encoded"));
+            }
+        }
+        _ => {}
+    }
+    let snr = snr(&a.samples.raw_data(), &b.samples.raw_data());
+    if snr >= MIN_SNR_DB {
+        diff.lossy
+            .push(format!("{prefix}audio re-encoded ({snr:.1} dB SNR)"));
+    } else {
+        diff.fail
+            .push(format!("{prefix}audio differs ({snr:.1} dB SNR)"));
+    }
+}
+
+fn compare_instrument(a: &Instrument, b: &Instrument) -> Outcome {
+    let mut diff = Diff::default();
+    diff.field("patchmaps", a.patchmaps.len(), b.patchmaps.len());
+    for (index, (a, b)) in a.patchmaps.iter().zip(&b.patchmaps).enumerate() {
+        let p = format!("patchmap {index} ");
+        diff.field(format_args!("{p}priority"), a.priority, b.priority);
+        diff.field(format_args!("{p}volume"), a.volume, b.volume);
+        diff.field(format_args!("{p}pan"), a.pan, b.pan);
+        diff.field(format_args!("{p}reverb"), a.reverb, b.reverb);
+        diff.field(format_args!("{p}root_key"), a.root_key, b.root_key);
+        diff.field(format_args!("{p}fine_adj"), a.fine_adj, b.fine_adj);
+        diff.field(format_args!("{p}note_min"), a.note_min, b.note_min);
+        diff.field(format_args!("{p}note_max"), a.note_max, b.note_max);
+        diff.field(
+            format_args!("{p}pitchstep_min"),
+            a.pitchstep_min,
+            b.pitchstep_min,
+        );
+        diff.field(
+            format_args!("{p}pitchstep_max"),
+            a.pitchstep_max,
+            b.pitchstep_max,
+        );
+        diff.field(format_args!("{p}attack_time"), a.attack_time, b.attack_time);
+        diff.field(format_args!("{p}decay_time"), a.decay_time, b.decay_time);
+        diff.field(
+            format_args!("{p}release_time"),
+            a.release_time,
+            b.release_time,
+        );
+        diff.field(
+            format_args!("{p}attack_level"),
+            a.attack_level,
+            b.attack_level,
+        );
+        diff.field(format_args!("{p}decay_level"), a.decay_level, b.decay_level);
+        match (&a.sample, &b.sample) {
+            (Some(a), Some(b)) => compare_sample(&mut diff, &p, &a.borrow(), &b.borrow()),
+            (a, b) => diff.field(format_args!("{p}sample"), a.is_some(), b.is_some()),
+        }
+    }
+    diff.outcome()
+}
+
+fn compare_effect(a: &Sample, b: &Sample) -> Outcome {
+    let mut diff = Diff::default();
+    diff.field("priority", a.priority, b.priority);
+    diff.field("volume", a.volume, b.volume);
+    compare_sample(&mut diff, "", &a.info, &b.info);
+    diff.outcome()
+}
+
+fn compare_music(a: &MusicSequence, b: &MusicSequence) -> Outcome {
+    let mut diff = Diff::default();
+    diff.field("tracks", a.tracks.len(), b.tracks.len());
+    for (index, (a, b)) in a.tracks.iter().zip(&b.tracks).enumerate() {
+        let p = format!("track {index} ");
+        diff.field(format_args!("{p}voices_type"), a.voices_type, b.voices_type);
+        diff.field(format_args!("{p}reverb"), a.reverb, b.reverb);
+        diff.field(
+            format_args!("{p}initpatchnum"),
+            a.initpatchnum,
+            b.initpatchnum,
+        );
+        diff.field(
+            format_args!("{p}initpitch_cntrl"),
+            a.initpitch_cntrl,
+            b.initpitch_cntrl,
+        );
+        diff.field(
+            format_args!("{p}initvolume_cntrl"),
+            a.initvolume_cntrl,
+            b.initvolume_cntrl,
+        );
+        diff.field(
+            format_args!("{p}initpan_cntrl"),
+            a.initpan_cntrl,
+            b.initpan_cntrl,
+        );
+        diff.field(
+            format_args!("{p}substack_count"),
+            a.substack_count,
+            b.substack_count,
+        );
+        diff.field(format_args!("{p}mutebits"), a.mutebits, b.mutebits);
+        diff.field(format_args!("{p}initppq"), a.initppq, b.initppq);
+        diff.field(format_args!("{p}initqpm"), a.initqpm, b.initqpm);
+        diff.field(format_args!("{p}labels"), &a.labels, &b.labels);
+        if a.events.len() != b.events.len() {
+            diff.field(format_args!("{p}events"), a.events.len(), b.events.len());
+        } else if let Some(index) = a.events.iter().zip(&b.events).position(|(a, b)| a != b) {
+            diff.field(
+                format_args!("{p}event {index}"),
+                &a.events[index],
+                &b.events[index],
+            );
+        }
+    }
+    diff.outcome()
+}
+
+/// Compares two map WADs lump by lump
+fn compare_map(a: &[u8], b: &[u8]) -> Outcome {
+    let parse = |data: &[u8]| {
+        FlatWad::parse(data, WadType::N64Map, true, &Default::default())
+            .map(|w| w.1)
+            .map_err(|e| crate::convert_error(data, e))
+    };
+    let (a, b) = match (parse(a), parse(b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) => return Outcome::Fail(format!("original map unreadable: {e}")),
+        (_, Err(e)) => return Outcome::Fail(format!("rebuilt map unreadable: {e}")),
+    };
+    let names = |wad: &FlatWad| {
+        wad.entries
+            .iter()
+            .map(|e| e.name.display().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let mut diff = Diff::default();
+    diff.field("lumps", names(&a), names(&b));
+    let maps = Map::read(&a).ok().zip(Map::read(&b).ok());
+    for (a, b) in a.entries.iter().zip(&b.entries) {
+        if a.name == b.name && a.entry.data != b.entry.data {
+            let name = a.name.display();
+            let record = maps
+                .as_ref()
+                .and_then(|(a, b)| first_record_diff(a, b, &name));
+            match record {
+                Some(index) => diff
+                    .fail
+                    .push(format!("lump {name} differs at record {index}")),
+                None => diff.fail.push(format!("lump {name} differs")),
+            }
+        }
+    }
+    diff.outcome()
+}
+
+/// Index of the first record that differs in a map lump made of records
+fn first_record_diff(a: &Map, b: &Map, lump: &str) -> Option<usize> {
+    fn first<T: PartialEq>(a: &[T], b: &[T]) -> Option<usize> {
+        let index = a.iter().zip(b).position(|(a, b)| a != b);
+        index.or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+    }
+    match lump {
+        "THINGS" => first(&a.things, &b.things),
+        "LINEDEFS" => first(&a.linedefs, &b.linedefs),
+        "SIDEDEFS" => first(&a.sidedefs, &b.sidedefs),
+        "VERTEXES" => first(&a.vertexes, &b.vertexes),
+        "SEGS" => first(&a.segs, &b.segs),
+        "SSECTORS" => first(&a.subsectors, &b.subsectors),
+        "NODES" => first(&a.nodes, &b.nodes),
+        "SECTORS" => first(&a.sectors, &b.sectors),
+        "LEAFS" => first(&a.leafs, &b.leafs),
+        "LIGHTS" => first(&a.lights, &b.lights),
+        _ => None,
+    }
+}
+
+fn compare_wads(a: &FlatWad, b: &FlatWad, report: &mut Report) -> io::Result<()> {
+    let key = |index: usize, wad: &FlatWad| {
+        let entry = &wad.entries[index];
+        (entry.entry.typ != LumpType::Marker).then(|| (entry.entry.typ, entry.name.clone()))
+    };
+    let original = (0..a.entries.len())
+        .filter_map(|index| key(index, a))
+        .collect::<HashSet<_>>();
+    // positions only count entries present in both, so one missing entry doesn't move the rest
+    let mut rebuilt = HashMap::new();
+    let mut common = 0;
+    for index in 0..b.entries.len() {
+        if let Some(key) = key(index, b) {
+            let position = original.contains(&key).then(|| {
+                common += 1;
+                common - 1
+            });
+            rebuilt.insert(key, (index, position));
+        }
+    }
+    let mut apalettes = PaletteCache::default();
+    let mut bpalettes = PaletteCache::default();
+    let mut position = 0;
+    for aindex in 0..a.entries.len() {
+        let Some(key) = key(aindex, a) else {
+            continue;
+        };
+        let name = key.1.display().into_owned();
+        let Some((bindex, bposition)) = rebuilt.remove(&key) else {
+            report.add(
+                "WAD",
+                name,
+                Outcome::Fail("missing from rebuilt IWAD".into()),
+            );
+            continue;
+        };
+        let adata = a.extract_one(aindex, &mut apalettes, false)?;
+        let bdata = b.extract_one(bindex, &mut bpalettes, false)?;
+        let outcome = if bposition != Some(position) {
+            Outcome::Fail(format!(
+                "moved from entry {position} to {}",
+                bposition.unwrap_or_default()
+            ))
+        } else if adata == bdata {
+            Outcome::Pass
+        } else if key.0 == LumpType::Map {
+            compare_map(&adata, &bdata)
+        } else {
+            Outcome::Fail(format!(
+                "decoded data differs ({} != {} bytes)",
+                adata.len(),
+                bdata.len()
+            ))
+        };
+        position += 1;
+        report.add("WAD", name, outcome);
+    }
+    let mut extra = rebuilt.into_iter().collect::<Vec<_>>();
+    extra.sort_by_key(|(_, (index, _))| *index);
+    for ((_, name), _) in extra {
+        report.add(
+            "WAD",
+            name.display(),
+            Outcome::Fail("not in original IWAD".into()),
+        );
+    }
+    Ok(())
+}
+
+fn compare_sound(a: &SoundData, b: &SoundData, report: &mut Report) {
+    for (id, inst) in &a.instruments {
+        let outcome = match b.instruments.get(id) {
+            Some(binst) => compare_instrument(inst, binst),
+            None => Outcome::Fail("missing from rebuilt WMD".into()),
+        };
+        report.add("INSTRUMENT", id, outcome);
+    }
+    for id in b.instruments.keys() {
+        if !a.instruments.contains_key(id) {
+            report.add(
+                "INSTRUMENT",
+                id,
+                Outcome::Fail("not in original WMD".into()),
+            );
+        }
+    }
+    for (id, seq) in &a.sequences {
+        let kind = match seq {
+            Sequence::Effect(_) => "EFFECT",
+            _ => "MUSIC",
+        };
+        let outcome = match (seq, b.sequences.get(id)) {
+            (_, None) => Outcome::Fail("missing from rebuilt WSD".into()),
+            (Sequence::Effect(a), Some(Sequence::Effect(b))) => compare_effect(a, b),
+            (Sequence::MusicSeq(a), Some(Sequence::MusicSeq(b))) => compare_music(a, b),
+            _ => Outcome::Fail("sequence type differs".into()),
+        };
+        report.add(kind, id, outcome);
+    }
+    for id in b.sequences.keys() {
+        if !a.sequences.contains_key(id) {
+            report.add("SEQUENCE", id, Outcome::Fail("not in original WSD".into()));
+        }
+    }
+}
+
+/// Writes the blake3 hashes of `files` in the format of `b3sum`, with paths relative to the
+/// manifest
+pub(crate) fn write_manifest(manifest: &Path, files: &[&Path]) -> io::Result<()> {
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let mut out = io::BufWriter::new(std::fs::File::create(manifest)?);
+    for path in files {
+        let hash = blake3::hash(&std::fs::read(path)?);
+        let name = path.strip_prefix(dir).unwrap_or(path);
+        writeln!(out, "{hash}  {}", name.display())?;
+    }
+    out.flush()
+}
+
+fn check_manifest(manifest: &Path, report: &mut Report) -> io::Result<()> {
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let text = std::fs::read_to_string(manifest)?;
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (hash, name) = line
+            .split_once("  ")
+            .and_then(|(hash, name)| Some((blake3::Hash::from_hex(hash).ok()?, name)))
+            .ok_or_else(|| {
+                invalid_data(format_args!(
+                    "{}:{}: expected a hash and a file name",
+                    manifest.display(),
+                    index + 1
+                ))
+            })?;
+        let outcome = match std::fs::read(dir.join(name)) {
+            Ok(data) if blake3::hash(&data) == hash => Outcome::Pass,
+            Ok(data) => Outcome::Fail(format!("hash is {}", blake3::hash(&data))),
+            Err(e) => Outcome::Fail(e.to_string()),
+        };
+        report.add("OUTPUT", name, outcome);
+    }
+    Ok(())
+}
+
+fn round_trip(
+    args: &Args,
+    input: &Path,
+    paths: ReadPaths,
+    workdir: &Path,
+    report: &mut Report,
+) -> io::Result<()> {
+    let extracted = workdir.join("DOOM64");
+    let rebuilt = workdir.join("DOOM64.WAD");
+    extract::extract(extract::Args::new(
+        input.to_owned(),
+        extracted.clone(),
+        paths,
+    ))?;
+    crate::build::build(crate::build::Args::new(vec![extracted], rebuilt.clone()))?;
+
+    let flags = ReadFlags::IWAD | ReadFlags::SOUND;
+    let paths = ReadPaths {
+        wdd: args.wdd.clone(),
+        wmd: args.wmd.clone(),
+        wsd: args.wsd.clone(),
+        dls: args.dls.clone(),
+        ..Default::default()
+    };
+    let (awad, asnd) = extract::read_rom_or_iwad(input, flags, &paths)?;
+    let (bwad, bsnd) = extract::read_rom_or_iwad(&rebuilt, flags, &Default::default())?;
+
+    log::info!("  RESULT KIND       NAME     DETAILS");
+    compare_wads(&awad.unwrap(), &bwad.unwrap(), report)?;
+    if let (Some(asnd), Some(bsnd)) = (asnd, bsnd) {
+        compare_sound(&asnd, &bsnd, report);
+    }
+    Ok(())
+}
+
+/// Extracts and rebuilds `input` in a working directory, comparing the result with it
+fn verify_round_trip(args: &Args, input: &Path, report: &mut Report) -> io::Result<()> {
+    let temporary = args.workdir.is_none();
+    let workdir = args.workdir.clone().unwrap_or_else(|| {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        std::env::temp_dir().join(format!("d64make-verify-{}-{nanos}", std::process::id()))
+    });
+    std::fs::create_dir_all(&workdir)?;
+    let paths = ReadPaths {
+        wdd: args.wdd.clone(),
+        wmd: args.wmd.clone(),
+        wsd: args.wsd.clone(),
+        dls: args.dls.clone(),
+        ..Default::default()
+    };
+    let res = round_trip(args, input, paths, &workdir, report);
+    if temporary && !args.keep {
+        if let Err(e) = std::fs::remove_dir_all(&workdir) {
+            log::warn!("Failed to remove `{}`: {e}", workdir.display());
+        }
+    } else {
+        log::info!("Kept working files in `{}`", workdir.display());
+    }
+    res
+}
+
+pub fn verify(args: Args) -> io::Result<()> {
+    let mut report = Report::default();
+    if let Some(manifest) = &args.manifest {
+        log::info!("Checking `{}`", manifest.display());
+        log::info!("  RESULT KIND       NAME     DETAILS");
+        check_manifest(manifest, &mut report)?;
+    }
+    if let Some(input) = &args.input {
+        verify_round_trip(&args, input, &mut report)?;
+    }
+    log::info!(
+        "Verified {} assets: {} passed, {} lossy, {} failed",
+        report.passed + report.lossy + report.failed,
+        report.passed,
+        report.lossy,
+        report.failed,
+    );
+    if report.failed > 0 {
+        return Err(invalid_data(format_args!(
+            "{} assets failed verification",
+            report.failed
+        )));
+    }
+    Ok(())
+}
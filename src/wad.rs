@@ -2,7 +2,7 @@ use crate::{convert_error, extract::WadType, gfx, invalid_data};
 use arrayvec::ArrayVec;
 use indexmap::IndexMap;
 use nom::error::{context, VerboseError};
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, rc::Rc};
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct EntryName(pub ArrayVec<u8, 8>);
@@ -20,6 +20,9 @@ pub struct Wad {
     pub hud_graphics: EntryMap<gfx::Sprite>,
     pub skies: EntryMap<gfx::Sprite>,
     pub other: EntryMap<Vec<u8>>,
+    /// Textures and flats of the WAD each map was made for, in `T_START` order, so map
+    /// texture indices can be rewritten when that order changes
+    pub map_textures: HashMap<EntryName, Rc<Vec<(LumpType, EntryName)>>>,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -27,7 +30,7 @@ pub struct FlatWad {
     pub entries: Vec<FlatEntry<Vec<u8>>>,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub enum LumpType {
     Unknown,
     Marker,
@@ -68,6 +71,14 @@ impl Compression {
             Self::Huffman(_) => "D64",
         }
     }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "None" => Some(Self::None),
+            "Jaguar" => Some(Self::Lzss(0)),
+            "D64" => Some(Self::Huffman(0)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -94,6 +105,29 @@ pub struct FlatEntry<T> {
 }
 
 impl LumpType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        use LumpType::*;
+        Some(match name {
+            "Unknown" => Unknown,
+            "Marker" => Marker,
+            "Sprite" => Sprite,
+            "Palette" => Palette,
+            "Texture" => Texture,
+            "Flat" => Flat,
+            "Graphic" => Graphic,
+            "HudGraphic" => HudGraphic,
+            "Sky" => Sky,
+            "Fire" => Fire,
+            "Cloud" => Cloud,
+            "Map" => Map,
+            "MapLump" => MapLump,
+            "Demo" => Demo,
+            "Sample" => Sample,
+            "SoundFont" => SoundFont,
+            "Sequence" => Sequence,
+            _ => return None,
+        })
+    }
     pub fn compression(&self) -> Compression {
         match self {
             Self::Map | Self::Demo | Self::Texture | Self::Flat => Compression::Huffman(0),
@@ -134,6 +168,12 @@ impl Wad {
                 replace(a, name, entry);
             }
         }
+        for name in other.maps.keys() {
+            match other.map_textures.get(name) {
+                Some(names) => self.map_textures.insert(name.clone(), names.clone()),
+                None => self.map_textures.remove(name),
+            };
+        }
         merge(&mut self.maps, other.maps);
         merge(&mut self.palettes, other.palettes);
         merge(&mut self.sprites, other.sprites);
@@ -226,14 +266,17 @@ impl Wad {
         }
         Ok(())
     }
+    /// Merges every entry of `other`, passing failed entries to `on_error`, which can return
+    /// the error to stop merging
     #[inline]
-    pub fn merge_flat(&mut self, other: FlatWad, ignore_errors: bool) -> std::io::Result<()> {
+    pub fn merge_flat(
+        &mut self,
+        other: FlatWad,
+        mut on_error: impl FnMut(std::io::Error) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
         for FlatEntry { name, entry } in other.entries {
             if let Err(err) = self.merge_one(name, entry) {
-                match ignore_errors {
-                    true => log::warn!("{err}"),
-                    false => return Err(err),
-                }
+                on_error(err)?;
             }
         }
         Ok(())
@@ -308,6 +351,24 @@ impl WadEntry<Vec<u8>> {
             data,
         }))
     }
+    /// Compresses the data if it isn't already compressed
+    pub fn compress(&mut self, compression: Compression) {
+        if self.compression.is_compressed() {
+            return;
+        }
+        let len = self.data.len();
+        match compression {
+            Compression::None => {}
+            Compression::Lzss(_) => {
+                self.data = crate::compression::encode_jaguar(&self.data);
+                self.compression = Compression::Lzss(len);
+            }
+            Compression::Huffman(_) => {
+                self.data = crate::compression::encode_d64(&self.data);
+                self.compression = Compression::Huffman(len);
+            }
+        }
+    }
 }
 
 impl EntryName {
@@ -319,6 +380,63 @@ impl EntryName {
     pub fn display(&self) -> std::borrow::Cow<str> {
         String::from_utf8_lossy(&self.0)
     }
+    /// Decodes a file name written by [`encode_file_name`], returning `None` if the decoded
+    /// name is too long
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let name = name.as_bytes();
+        let mut decoded = ArrayVec::new();
+        let mut i = 0;
+        while i < name.len() {
+            let escaped = (name[i] == b'%')
+                .then(|| name.get(i + 1..i + 3))
+                .flatten()
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match escaped {
+                Some(b) => {
+                    decoded.try_push(b).ok()?;
+                    i += 3;
+                }
+                None => {
+                    decoded.try_push(name[i]).ok()?;
+                    i += 1;
+                }
+            }
+        }
+        Some(Self(decoded))
+    }
+}
+
+/// Bytes besides uppercase letters and digits that are kept as-is in file names
+const FILE_NAME_SAFE: &[u8] = b"_-[]()!$&+,;=@^~";
+
+/// Device names that can't be used as file names on Windows, with or without an extension
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Encodes an entry name as a file name that is valid and distinct on every platform and
+/// filesystem, including case-insensitive ones. Bytes other than uppercase letters, digits
+/// and `_-[]()!$&+,;=@^~` are written as `%` followed by two hex digits, as is the first
+/// letter of a reserved Windows device name like `CON`.
+pub fn encode_file_name(name: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let reserved = RESERVED_FILE_NAMES
+        .iter()
+        .any(|r| r.as_bytes().eq_ignore_ascii_case(name));
+    let mut encoded = String::with_capacity(name.len());
+    for (index, b) in name.iter().copied().enumerate() {
+        let safe = b.is_ascii_uppercase() || b.is_ascii_digit() || FILE_NAME_SAFE.contains(&b);
+        if safe && !(reserved && index == 0) {
+            encoded.push(b as char);
+        } else {
+            write!(encoded, "%{b:02X}").unwrap();
+        }
+    }
+    encoded
 }
 
 impl std::borrow::Borrow<[u8]> for EntryName {